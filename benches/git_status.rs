@@ -0,0 +1,123 @@
+//! Times the prompt in a generated repository with many files, with the
+//! default git status options, with the slow parts turned off, and with the
+//! daemon answering.
+//!
+//! Run with `cargo bench --bench git_status`. Set `RPS_BENCH_FILES` to change
+//! the size of the repository.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use git2::{IndexAddOption, Repository, Signature};
+
+const DEFAULT_FILE_COUNT: usize = 50_000;
+const FILES_PER_DIR: usize = 100;
+const ITERATIONS: usize = 10;
+
+fn generate_repo(root: &Path, file_count: usize) {
+    let repo = Repository::init(root).expect("init repository");
+    for i in 0..file_count {
+        let dir = root.join(format!("dir{}", i / FILES_PER_DIR));
+        if i % FILES_PER_DIR == 0 {
+            fs::create_dir_all(&dir).unwrap();
+        }
+        fs::write(dir.join(format!("file{}.txt", i)), format!("{}\n", i)).unwrap();
+    }
+
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("bench", "bench@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+        .unwrap();
+
+    // Some modified and untracked files so the status has work to report
+    for i in (0..file_count).step_by(FILES_PER_DIR * 10) {
+        let dir = root.join(format!("dir{}", i / FILES_PER_DIR));
+        fs::write(dir.join(format!("file{}.txt", i)), "changed\n").unwrap();
+        fs::write(dir.join(format!("new{}.txt", i)), "new\n").unwrap();
+    }
+}
+
+/// `runtime_dir` is where the prompt looks for the daemon's socket
+fn time_prompt(repo: &Path, config: &Path, runtime_dir: &Path) -> Vec<Duration> {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            let output = Command::new(env!("CARGO_BIN_EXE_rps"))
+                .current_dir(repo)
+                .env("XDG_RUNTIME_DIR", runtime_dir)
+                .arg("--columns=200")
+                .arg("--config")
+                .arg(config)
+                .output()
+                .expect("run rps");
+            assert!(output.status.success());
+            start.elapsed()
+        })
+        .collect()
+}
+
+fn report(name: &str, mut times: Vec<Duration>) {
+    times.sort();
+    let total: Duration = times.iter().sum();
+    println!(
+        "{:<24} min {:>8.1?}  median {:>8.1?}  mean {:>8.1?}",
+        name,
+        times[0],
+        times[times.len() / 2],
+        total / times.len() as u32
+    );
+}
+
+fn main() {
+    let file_count = std::env::var("RPS_BENCH_FILES")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_FILE_COUNT);
+
+    let root = std::env::temp_dir().join(format!("rps-bench-{}", std::process::id()));
+    let repo = root.join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    println!("generating repository with {} files", file_count);
+    generate_repo(&repo, file_count);
+
+    let cases = [
+        ("default", "[git]\nstatus_timeout_ms = 0\n"),
+        (
+            "no untracked",
+            "[git]\nstatus_timeout_ms = 0\ninclude_untracked = false\n",
+        ),
+        ("timeout 50ms", "[git]\nstatus_timeout_ms = 50\n"),
+    ];
+    for (name, config) in cases {
+        let config_path: PathBuf = root.join(format!("{}.toml", name.replace(' ', "_")));
+        fs::write(&config_path, config).unwrap();
+        // Warm up the file system cache
+        time_prompt(&repo, &config_path, &root);
+        report(name, time_prompt(&repo, &config_path, &root));
+    }
+
+    let config_path = root.join("default.toml");
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_rps"))
+        .arg("daemon")
+        .env("XDG_RUNTIME_DIR", &root)
+        .spawn()
+        .expect("start daemon");
+    thread::sleep(Duration::from_millis(200));
+    // The first prompt fills the daemon's cache
+    time_prompt(&repo, &config_path, &root);
+    report("daemon", time_prompt(&repo, &config_path, &root));
+    let _ = daemon.kill();
+    let _ = daemon.wait();
+
+    let _ = fs::remove_dir_all(&root);
+}
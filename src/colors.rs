@@ -1,81 +1,367 @@
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Color {
-    pub fg: u8,
-    pub bg: u8,
-    pub fgb: u8,
-    pub bgb: u8,
-    pub name: &'static str,
+use std::borrow::Cow;
+
+use crate::theme::Theme;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// The terminal's default foreground or background
+    Default,
+    /// One of the 16 basic colors: 0-7 are normal and 8-15 are bright
+    Ansi(u8),
+    /// An entry of the 256-color palette
+    Indexed(u8),
+    Rgb(u8, u8, u8),
 }
 
 #[allow(unused)]
-pub const BLACK: Color = Color {
-    fg: 30,
-    bg: 40,
-    fgb: 90,
-    bgb: 100,
-    name: "black",
-};
-#[allow(unused)]
-pub const RED: Color = Color {
-    fg: 31,
-    bg: 41,
-    fgb: 91,
-    bgb: 101,
-    name: "red",
-};
-#[allow(unused)]
-pub const GREEN: Color = Color {
-    fg: 32,
-    bg: 42,
-    fgb: 92,
-    bgb: 102,
-    name: "green",
-};
-#[allow(unused)]
-pub const YELLOW: Color = Color {
-    fg: 33,
-    bg: 43,
-    fgb: 93,
-    bgb: 103,
-    name: "yellow",
-};
-#[allow(unused)]
-pub const BLUE: Color = Color {
-    fg: 34,
-    bg: 44,
-    fgb: 94,
-    bgb: 104,
-    name: "blue",
-};
-#[allow(unused)]
-pub const MAGENTA: Color = Color {
-    fg: 35,
-    bg: 45,
-    fgb: 95,
-    bgb: 105,
-    name: "magenta",
-};
-#[allow(unused)]
-pub const CYAN: Color = Color {
-    fg: 36,
-    bg: 46,
-    fgb: 96,
-    bgb: 106,
-    name: "cyan",
-};
-#[allow(unused)]
-pub const WHITE: Color = Color {
-    fg: 37,
-    bg: 47,
-    fgb: 97,
-    bgb: 107,
-    name: "white",
-};
-#[allow(unused)]
-pub const DEFAULT: Color = Color {
-    fg: 39,
-    bg: 49,
-    fgb: 99,
-    bgb: 109,
-    name: "",
-};
+pub const BLACK: Color = Color::Ansi(0);
+#[allow(unused)]
+pub const RED: Color = Color::Ansi(1);
+#[allow(unused)]
+pub const GREEN: Color = Color::Ansi(2);
+#[allow(unused)]
+pub const YELLOW: Color = Color::Ansi(3);
+#[allow(unused)]
+pub const BLUE: Color = Color::Ansi(4);
+#[allow(unused)]
+pub const MAGENTA: Color = Color::Ansi(5);
+#[allow(unused)]
+pub const CYAN: Color = Color::Ansi(6);
+#[allow(unused)]
+pub const WHITE: Color = Color::Ansi(7);
+#[allow(unused)]
+pub const BRIGHT_BLACK: Color = Color::Ansi(8);
+#[allow(unused)]
+pub const BRIGHT_RED: Color = Color::Ansi(9);
+#[allow(unused)]
+pub const BRIGHT_GREEN: Color = Color::Ansi(10);
+#[allow(unused)]
+pub const BRIGHT_YELLOW: Color = Color::Ansi(11);
+#[allow(unused)]
+pub const BRIGHT_BLUE: Color = Color::Ansi(12);
+#[allow(unused)]
+pub const BRIGHT_MAGENTA: Color = Color::Ansi(13);
+#[allow(unused)]
+pub const BRIGHT_CYAN: Color = Color::Ansi(14);
+#[allow(unused)]
+pub const BRIGHT_WHITE: Color = Color::Ansi(15);
+#[allow(unused)]
+pub const DEFAULT: Color = Color::Default;
+
+/// xterm's default values for the 16 basic colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Channel values of the 6x6x6 color cube in the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+pub fn detect_color_depth(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+    if matches!(colorterm, Some("truecolor") | Some("24bit")) {
+        return ColorDepth::TrueColor;
+    }
+    match term {
+        Some(term) if term.ends_with("-direct") => ColorDepth::TrueColor,
+        Some(term) if term.contains("256color") => ColorDepth::Ansi256,
+        _ => ColorDepth::Basic,
+    }
+}
+
+pub fn get_color_depth() -> ColorDepth {
+    let colorterm = std::env::var("COLORTERM").ok();
+    let term = std::env::var("TERM").ok();
+    detect_color_depth(colorterm.as_deref(), term.as_deref())
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn indexed_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_RGB[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        232..=255 => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn rgb_to_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let nearest_level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| (CUBE_LEVELS[*i] as i32 - c as i32).abs())
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * nearest_level(rgb.0) + 6 * nearest_level(rgb.1) + nearest_level(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance(indexed_to_rgb(gray), rgb) < distance(indexed_to_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_to_ansi(rgb: (u8, u8, u8)) -> u8 {
+    (0..ANSI_RGB.len())
+        .min_by_key(|i| distance(ANSI_RGB[*i], rgb))
+        .unwrap() as u8
+}
+
+impl Color {
+    /// Approximates this color with one the terminal can show
+    pub fn degrade(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Rgb(r, g, b), ColorDepth::Ansi256) => Color::Indexed(rgb_to_indexed((r, g, b))),
+            (Color::Rgb(r, g, b), ColorDepth::Basic) => Color::Ansi(rgb_to_ansi((r, g, b))),
+            (Color::Indexed(i), ColorDepth::Basic) if i < 16 => Color::Ansi(i),
+            (Color::Indexed(i), ColorDepth::Basic) => Color::Ansi(rgb_to_ansi(indexed_to_rgb(i))),
+            (color, _) => color,
+        }
+    }
+
+    /// SGR parameters that set this as the foreground color
+    pub fn fg_params(&self) -> String {
+        match *self {
+            Color::Default => String::from("39"),
+            Color::Ansi(n) if n < 8 => format!("{}", 30 + n),
+            Color::Ansi(n) => format!("{}", 90 + n % 8),
+            Color::Indexed(n) => format!("38;5;{}", n),
+            Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+        }
+    }
+
+    /// SGR parameters that set this as the background color
+    pub fn bg_params(&self) -> String {
+        match *self {
+            Color::Default => String::from("49"),
+            Color::Ansi(n) if n < 8 => format!("{}", 40 + n),
+            Color::Ansi(n) => format!("{}", 100 + n % 8),
+            Color::Indexed(n) => format!("48;5;{}", n),
+            Color::Rgb(r, g, b) => format!("48;2;{};{};{}", r, g, b),
+        }
+    }
+
+    /// Escape sequence that switches the foreground color in the middle of a
+    /// segment's text. It is translated through the theme when printed.
+    pub fn fg_sequence(&self) -> String {
+        format!("\x1b[{}m", self.fg_params())
+    }
+}
+
+/// Parses the color at the start of `params`, returning it, whether it is a
+/// background color, and how many parameters it used
+fn parse_color_params(params: &[&str]) -> Option<(Color, bool, usize)> {
+    let number = |i: usize| params.get(i)?.parse::<u8>().ok();
+    let code = number(0)?;
+    match code {
+        30..=37 => Some((Color::Ansi(code - 30), false, 1)),
+        40..=47 => Some((Color::Ansi(code - 40), true, 1)),
+        90..=97 => Some((Color::Ansi(code - 90 + 8), false, 1)),
+        100..=107 => Some((Color::Ansi(code - 100 + 8), true, 1)),
+        39 => Some((Color::Default, false, 1)),
+        49 => Some((Color::Default, true, 1)),
+        38 | 48 => match number(1)? {
+            5 => Some((Color::Indexed(number(2)?), code == 48, 3)),
+            2 => Some((
+                Color::Rgb(number(2)?, number(3)?, number(4)?),
+                code == 48,
+                5,
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The theme and color depth that colors are printed with
+pub struct ColorScheme {
+    pub theme: &'static Theme,
+    pub depth: ColorDepth,
+}
+
+impl ColorScheme {
+    pub fn resolve(&self, color: Color) -> Color {
+        self.theme.resolve(color).degrade(self.depth)
+    }
+
+    pub fn sequence(&self, fg: &Color, bg: &Color) -> String {
+        format!(
+            "\x1b[{}m\x1b[{}m",
+            self.resolve(*fg).fg_params(),
+            self.resolve(*bg).bg_params()
+        )
+    }
+
+    fn translate_sgr(&self, params: &str) -> String {
+        let params: Vec<&str> = params.split(';').collect();
+        let mut translated: Vec<String> = Vec::with_capacity(params.len());
+        let mut i = 0;
+        while i < params.len() {
+            match parse_color_params(&params[i..]) {
+                Some((color, is_bg, used)) => {
+                    let color = self.resolve(color);
+                    translated.push(if is_bg {
+                        color.bg_params()
+                    } else {
+                        color.fg_params()
+                    });
+                    i += used;
+                }
+                None => {
+                    translated.push(params[i].to_string());
+                    i += 1;
+                }
+            }
+        }
+        format!("\x1b[{}m", translated.join(";"))
+    }
+
+    /// Rewrites the colors of SGR sequences embedded in a segment's text
+    pub fn translate<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !text.contains("\x1b[") {
+            return Cow::Borrowed(text);
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("\x1b[") {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after.find(|c: char| ('\x40'..='\x7e').contains(&c));
+            match end {
+                Some(end) if after[end..].starts_with('m') => {
+                    result.push_str(&self.translate_sgr(&after[..end]));
+                    rest = &after[end + 1..];
+                }
+                _ => {
+                    result.push_str("\x1b[");
+                    rest = after;
+                }
+            }
+        }
+        result.push_str(rest);
+        Cow::Owned(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::theme;
+
+    use super::{detect_color_depth, Color, ColorDepth, ColorScheme, BLUE, BRIGHT_RED, DEFAULT};
+
+    #[test]
+    fn sgr_params() {
+        assert_eq!(BLUE.fg_params(), "34");
+        assert_eq!(BLUE.bg_params(), "44");
+        assert_eq!(BRIGHT_RED.fg_params(), "91");
+        assert_eq!(BRIGHT_RED.bg_params(), "101");
+        assert_eq!(DEFAULT.fg_params(), "39");
+        assert_eq!(Color::Indexed(208).fg_params(), "38;5;208");
+        assert_eq!(Color::Rgb(1, 2, 3).bg_params(), "48;2;1;2;3");
+    }
+
+    #[test]
+    fn detect_depth() {
+        assert_eq!(
+            detect_color_depth(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            detect_color_depth(Some("24bit"), None),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            detect_color_depth(None, Some("xterm-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            detect_color_depth(None, Some("xterm-direct")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(detect_color_depth(None, Some("linux")), ColorDepth::Basic);
+        assert_eq!(detect_color_depth(None, None), ColorDepth::Basic);
+    }
+
+    #[test]
+    fn degrade_rgb() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.degrade(ColorDepth::TrueColor), orange);
+        assert_eq!(orange.degrade(ColorDepth::Ansi256), Color::Indexed(208));
+        assert_eq!(
+            Color::Rgb(250, 10, 10).degrade(ColorDepth::Basic),
+            Color::Ansi(9)
+        );
+        assert_eq!(
+            Color::Rgb(128, 128, 128).degrade(ColorDepth::Ansi256),
+            Color::Indexed(244)
+        );
+    }
+
+    #[test]
+    fn degrade_indexed() {
+        assert_eq!(Color::Indexed(4).degrade(ColorDepth::Basic), BLUE);
+        assert_eq!(
+            Color::Indexed(196).degrade(ColorDepth::Basic),
+            Color::Ansi(9)
+        );
+        assert_eq!(
+            Color::Indexed(196).degrade(ColorDepth::Ansi256),
+            Color::Indexed(196)
+        );
+        assert_eq!(BLUE.degrade(ColorDepth::Basic), BLUE);
+    }
+
+    #[test]
+    fn translate_embedded_colors() {
+        let scheme = ColorScheme {
+            theme: &theme::HIGH_CONTRAST,
+            depth: ColorDepth::TrueColor,
+        };
+        assert_eq!(
+            scheme.translate("\x1b[1;31m✘\x1b[39m x"),
+            "\x1b[1;38;2;255;0;0m✘\x1b[39m x"
+        );
+        let basic = ColorScheme {
+            theme: &theme::DEFAULT,
+            depth: ColorDepth::Basic,
+        };
+        assert_eq!(
+            basic.translate("\x1b[38;5;196mx\x1b[0m"),
+            "\x1b[91mx\x1b[0m"
+        );
+        assert_eq!(basic.translate("plain"), "plain");
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::theme::{get_theme, THEMES};
+
+const CONFIG_DIR_NAME: &str = "rps";
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentKind {
+    Status,
+    Duration,
+    Jobs,
+    Path,
+    Git,
+    Host,
+    Python,
+    Rust,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    /// Fewest characters of the branch name to show before hiding it
+    pub min_branch_text: usize,
+    /// Show how many files are in each state next to the status symbols
+    pub show_counts: bool,
+    /// Milliseconds to wait for the status before showing it as unknown, or 0
+    /// to always wait
+    pub status_timeout_ms: u64,
+    /// Look for untracked files, which can be slow in large repositories
+    pub include_untracked: bool,
+    /// Skip checking submodules for changes
+    pub exclude_submodules: bool,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        GitConfig {
+            min_branch_text: 4,
+            show_counts: false,
+            status_timeout_ms: 500,
+            include_untracked: true,
+            exclude_submodules: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostConfig {
+    /// User name that isn't shown, unless it is root
+    pub default_user: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PythonConfig {
+    /// Show the environment's Python version, read from its files
+    pub show_version: bool,
+}
+
+impl Default for PythonConfig {
+    fn default() -> Self {
+        PythonConfig { show_version: true }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DurationConfig {
+    /// Shortest command duration to show
+    pub min_duration_ms: u64,
+}
+
+impl Default for DurationConfig {
+    fn default() -> Self {
+        DurationConfig {
+            min_duration_ms: 2000,
+        }
+    }
+}
+
+/// How the path is shortened when there isn't room for all of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStrategy {
+    /// Cut characters off the start of the path
+    Truncate,
+    /// Shorten parent directories to their first letters, like `~/p/r/src`
+    Abbreviate,
+    /// Shorten parent directories to the shortest prefix that no other
+    /// directory next to them starts with
+    Unique,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathConfig {
+    /// Smallest width the path can be truncated to before it is hidden
+    pub min_path_size: usize,
+    pub strategy: PathStrategy,
+    /// Letters to keep of each parent directory with the `abbreviate`
+    /// strategy, not counting a leading `.`
+    pub abbreviated_length: usize,
+    /// Inside a git repository, start the path at the repository's name
+    pub relative_to_repo: bool,
+    /// Text to show instead of the start of the path, by the directory it
+    /// replaces. A leading `~` is the home directory.
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        PathConfig {
+            min_path_size: 6,
+            strategy: PathStrategy::Truncate,
+            abbreviated_length: 1,
+            relative_to_repo: false,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Which segments to show, from left to right
+    pub segments: Vec<SegmentKind>,
+    /// Which segments to show in the right prompt, from left to right
+    pub right_segments: Vec<SegmentKind>,
+    /// Free columns to leave after the prompt before splitting it onto its
+    /// own line
+    pub min_whitespace: usize,
+    /// Name of the color theme
+    pub theme: String,
+    /// Milliseconds to wait for each segment to be built, or 0 to always wait
+    pub segment_timeout_ms: u64,
+    /// Show a placeholder for segments that timed out instead of leaving them
+    /// out
+    pub show_timed_out_segments: bool,
+    /// Replace the prompt with a short one once a command line is accepted,
+    /// in zsh and fish. Read when the init script is generated.
+    pub transient_prompt: bool,
+    /// Link path components to their directories and the git branch to its
+    /// page on the remote's website
+    pub hyperlinks: bool,
+    pub git: GitConfig,
+    pub path: PathConfig,
+    pub duration: DurationConfig,
+    pub host: HostConfig,
+    pub python: PythonConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            segments: vec![
+                SegmentKind::Status,
+                SegmentKind::Duration,
+                SegmentKind::Jobs,
+                SegmentKind::Path,
+                SegmentKind::Git,
+            ],
+            right_segments: Vec::new(),
+            min_whitespace: 40,
+            theme: String::from("default"),
+            segment_timeout_ms: 1000,
+            show_timed_out_segments: true,
+            transient_prompt: false,
+            hyperlinks: true,
+            git: GitConfig::default(),
+            path: PathConfig::default(),
+            duration: DurationConfig::default(),
+            host: HostConfig::default(),
+            python: PythonConfig::default(),
+        }
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ =>
+        {
+            #[allow(deprecated)]
+            std::env::home_dir()?.join(".config")
+        }
+    };
+    Some(config_home.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+fn parse_config(text: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+    if get_theme(&config.theme).is_none() {
+        let names: Vec<String> = THEMES.iter().map(|x| format!("`{}`", x.name)).collect();
+        return Err(format!(
+            "unknown theme `{}`, expected one of {}",
+            config.theme,
+            names.join(", ")
+        ));
+    }
+    Ok(config)
+}
+
+fn read_config(path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_config(&text)
+}
+
+/// Loads the config file given on the command line, or the one in the user's
+/// config directory if there is one. A missing default config file is not an
+/// error.
+pub fn load_config(explicit_path: Option<&Path>) -> Result<Config, String> {
+    match explicit_path {
+        Some(path) => read_config(path).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => match default_config_path() {
+            Some(path) if path.exists() => {
+                read_config(&path).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            _ => Ok(Config::default()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_config, Config, PathStrategy, SegmentKind};
+
+    #[test]
+    fn empty_config_is_default() {
+        assert_eq!(parse_config("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parse_segments_and_options() {
+        let config = parse_config(
+            "\
+            segments = [\"git\", \"path\"]\n\
+            right_segments = [\"status\"]\n\
+            min_whitespace = 10\n\
+            [git]\n\
+            min_branch_text = 8\n\
+            [path]\n\
+            min_path_size = 3\n\
+            strategy = \"unique\"\n\
+            ",
+        )
+        .unwrap();
+        assert_eq!(config.segments, vec![SegmentKind::Git, SegmentKind::Path]);
+        assert_eq!(config.right_segments, vec![SegmentKind::Status]);
+        assert_eq!(config.min_whitespace, 10);
+        assert_eq!(config.git.min_branch_text, 8);
+        assert_eq!(config.path.min_path_size, 3);
+        assert_eq!(config.path.strategy, PathStrategy::Unique);
+    }
+
+    #[test]
+    fn unknown_key_lists_valid_keys() {
+        let error = parse_config("min_whitespaec = 10").unwrap_err();
+        assert!(error.contains("min_whitespaec"), "{}", error);
+        assert!(error.contains("`min_whitespace`"), "{}", error);
+        assert!(error.contains("`segments`"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_segment_option_lists_valid_keys() {
+        let error = parse_config("[git]\nmin_branch = 10").unwrap_err();
+        assert!(error.contains("`min_branch_text`"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_segment_lists_valid_segments() {
+        let error = parse_config("segments = [\"time\"]").unwrap_err();
+        assert!(error.contains("time"), "{}", error);
+        assert!(error.contains("`status`"), "{}", error);
+        assert!(error.contains("`git`"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_theme_lists_valid_themes() {
+        assert_eq!(
+            parse_config("theme = \"gruvbox\"").unwrap().theme,
+            "gruvbox"
+        );
+        let error = parse_config("theme = \"monokai\"").unwrap_err();
+        assert!(error.contains("monokai"), "{}", error);
+        assert!(error.contains("`solarized`"), "{}", error);
+    }
+}
@@ -0,0 +1,548 @@
+//! `rps daemon` keeps repositories open and their state cached between
+//! prompts, and drops the cached state when inotify reports a change. Prompts
+//! send it a request over a Unix socket and fall back to reading the
+//! repository themselves when no daemon is running.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::{fs::MetadataExt, net::UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::GitConfig, git::GitInfo};
+
+const SOCKET_NAME: &str = "rps.sock";
+
+/// How long the daemon waits for a prompt to send its request
+#[cfg(target_os = "linux")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    path: PathBuf,
+    options: GitConfig,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Response {
+    /// Missing when the path isn't in a repository
+    info: Option<GitInfo>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// No daemon is running, or it sent something that couldn't be read
+    Unavailable,
+    /// The daemon didn't answer within the status timeout
+    Timeout,
+}
+
+/// `$XDG_RUNTIME_DIR`, or a per-user directory in the temp directory
+fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir().join(format!("rps-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Whether `dir` is a directory owned by this user that nobody else can
+/// write to, so that no one else can put a socket in it
+fn is_private(dir: &Path) -> bool {
+    match fs::symlink_metadata(dir) {
+        Ok(metadata) => {
+            metadata.is_dir()
+                && metadata.uid() == unsafe { libc::getuid() }
+                && metadata.mode() & 0o022 == 0
+        }
+        Err(_) => false,
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    socket_dir().join(SOCKET_NAME)
+}
+
+/// Asks the daemon about the repository containing `path`
+pub fn query(path: &Path, options: &GitConfig) -> Result<Option<GitInfo>, QueryError> {
+    // Another user could otherwise answer with a prompt of their choosing
+    if !is_private(&socket_dir()) {
+        return Err(QueryError::Unavailable);
+    }
+    let mut stream = UnixStream::connect(socket_path()).map_err(|_| QueryError::Unavailable)?;
+    if options.status_timeout_ms != 0 {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(options.status_timeout_ms)))
+            .map_err(|_| QueryError::Unavailable)?;
+    }
+
+    let request = toml::to_string(&Request {
+        path: path.to_path_buf(),
+        options: options.clone(),
+    })
+    .map_err(|_| QueryError::Unavailable)?;
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .map_err(|_| QueryError::Unavailable)?;
+
+    let mut response = String::new();
+    match stream.read_to_string(&mut response) {
+        Ok(_) => {}
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            return Err(QueryError::Timeout)
+        }
+        Err(_) => return Err(QueryError::Unavailable),
+    }
+    toml::from_str::<Response>(&response)
+        .map(|x| x.info)
+        .map_err(|_| QueryError::Unavailable)
+}
+
+#[cfg(target_os = "linux")]
+pub use server::run_daemon;
+
+#[cfg(target_os = "linux")]
+mod server {
+    use std::{
+        collections::HashMap,
+        ffi::OsStr,
+        fs::{self, DirBuilder},
+        io::{self, Read, Write},
+        os::unix::{
+            fs::DirBuilderExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use git2::Repository;
+    use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+
+    use crate::{
+        config::GitConfig,
+        git::{open_repository, GitInfo},
+    };
+
+    use super::{is_private, socket_dir, socket_path, Request, Response, REQUEST_TIMEOUT};
+
+    const WATCH_MASK: WatchMask = WatchMask::CREATE
+        .union(WatchMask::DELETE)
+        .union(WatchMask::MODIFY)
+        .union(WatchMask::ATTRIB)
+        .union(WatchMask::MOVED_FROM)
+        .union(WatchMask::MOVED_TO)
+        .union(WatchMask::DELETE_SELF)
+        .union(WatchMask::MOVE_SELF)
+        .union(WatchMask::ONLYDIR)
+        .union(WatchMask::DONT_FOLLOW);
+
+    struct CachedRepo {
+        /// Checks new directories against `.gitignore`
+        repo: Repository,
+        /// A second handle that status is read with outside the cache lock,
+        /// so a slow repository doesn't hold up prompts in other ones
+        status_repo: Arc<Mutex<Repository>>,
+        /// Options the cached info was read with
+        options: GitConfig,
+        info: Option<GitInfo>,
+        /// Counts changes, so that info read while one happened isn't cached
+        generation: u64,
+        /// False if some directories couldn't be watched, in which case
+        /// nothing is cached
+        watched: bool,
+    }
+
+    impl CachedRepo {
+        fn invalidate(&mut self) {
+            self.info = None;
+            self.generation += 1;
+        }
+    }
+
+    /// A watched directory
+    struct Watch {
+        git_dir: PathBuf,
+        dir: PathBuf,
+        /// Whether new subdirectories are watched too
+        recursive: bool,
+    }
+
+    #[derive(Default)]
+    struct Cache {
+        /// Open repositories by git directory
+        repos: HashMap<PathBuf, CachedRepo>,
+        /// Git directory of the repository containing each requested path
+        paths: HashMap<PathBuf, PathBuf>,
+        watches: HashMap<WatchDescriptor, Watch>,
+    }
+
+    /// The directory holding refs, which linked worktrees share with the main
+    /// repository
+    fn common_dir(git_dir: &Path) -> PathBuf {
+        match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(dir) => git_dir.join(dir.trim()),
+            Err(_) => git_dir.to_path_buf(),
+        }
+    }
+
+    fn is_ignored(repo: &Repository, path: &Path) -> bool {
+        if path.starts_with(repo.path()) {
+            return false;
+        }
+        match repo.workdir().and_then(|x| path.strip_prefix(x).ok()) {
+            Some(relative) => repo.is_path_ignored(relative).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Watches `dir` and, if `recursive`, every directory under it except
+    /// ignored ones and nested git directories
+    fn watch_dir(
+        repo: &Repository,
+        watches: &mut Watches,
+        watch_map: &mut HashMap<WatchDescriptor, Watch>,
+        git_dir: &Path,
+        dir: &Path,
+        recursive: bool,
+    ) -> io::Result<()> {
+        let descriptor = watches.add(dir, WATCH_MASK)?;
+        watch_map.insert(
+            descriptor,
+            Watch {
+                git_dir: git_dir.to_path_buf(),
+                dir: dir.to_path_buf(),
+                recursive,
+            },
+        );
+        if !recursive {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let is_dir = entry.file_type().map_or(false, |x| x.is_dir());
+            if is_dir && entry.file_name() != ".git" && !is_ignored(repo, &path) {
+                watch_dir(repo, watches, watch_map, git_dir, &path, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn watch_repository(
+        repo: &Repository,
+        watches: &mut Watches,
+        watch_map: &mut HashMap<WatchDescriptor, Watch>,
+    ) -> io::Result<()> {
+        let git_dir = repo.path();
+        let common_dir = common_dir(git_dir);
+        // HEAD, the index and the files marking a merge or rebase, but not
+        // the object database
+        watch_dir(repo, watches, watch_map, git_dir, git_dir, false)?;
+        if common_dir != git_dir {
+            watch_dir(repo, watches, watch_map, git_dir, &common_dir, false)?;
+        }
+        for dir in ["refs", "logs"] {
+            let dir = common_dir.join(dir);
+            if dir.is_dir() {
+                watch_dir(repo, watches, watch_map, git_dir, &dir, true)?;
+            }
+        }
+        if let Some(workdir) = repo.workdir() {
+            watch_dir(repo, watches, watch_map, git_dir, workdir, true)?;
+        }
+        Ok(())
+    }
+
+    /// Stops watching every directory of the repository at `git_dir`
+    fn unwatch_repository(
+        watches: &mut Watches,
+        watch_map: &mut HashMap<WatchDescriptor, Watch>,
+        git_dir: &Path,
+    ) {
+        watch_map.retain(|descriptor, watch| {
+            if watch.git_dir != git_dir {
+                return true;
+            }
+            let _ = watches.remove(descriptor.clone());
+            false
+        });
+    }
+
+    /// Opens and watches the repository containing `path`, returning its git
+    /// directory. Walking the work tree takes a while in large repositories,
+    /// so it is done before taking the cache lock.
+    fn open_repo(cache: &Mutex<Cache>, watches: &mut Watches, path: &Path) -> Option<PathBuf> {
+        let repo = open_repository(path)?;
+        let git_dir = repo.path().to_path_buf();
+        let opened = cache.lock().unwrap().repos.contains_key(&git_dir);
+        let mut watch_map = HashMap::new();
+        let watched = opened || watch_repository(&repo, watches, &mut watch_map).is_ok();
+
+        let mut cache = cache.lock().unwrap();
+        if !watched || cache.repos.contains_key(&git_dir) {
+            // Watching failed partway, or another prompt opened the
+            // repository in the meantime and already watches the same
+            // directories
+            for descriptor in watch_map.into_keys() {
+                if !cache.watches.contains_key(&descriptor) {
+                    let _ = watches.remove(descriptor);
+                }
+            }
+        } else {
+            cache.watches.extend(watch_map);
+        }
+        if !cache.repos.contains_key(&git_dir) {
+            let status_repo = Repository::open(&git_dir).ok()?;
+            cache.repos.insert(
+                git_dir.clone(),
+                CachedRepo {
+                    repo,
+                    status_repo: Arc::new(Mutex::new(status_repo)),
+                    options: GitConfig::default(),
+                    info: None,
+                    generation: 0,
+                    watched,
+                },
+            );
+        }
+        cache.paths.insert(path.to_path_buf(), git_dir.clone());
+        Some(git_dir)
+    }
+
+    fn get_info(cache: &Mutex<Cache>, watches: &mut Watches, request: Request) -> Option<GitInfo> {
+        let known = cache.lock().unwrap().paths.get(&request.path).cloned();
+        let git_dir = match known {
+            Some(git_dir) => git_dir,
+            None => open_repo(cache, watches, &request.path)?,
+        };
+
+        let (status_repo, generation) = {
+            let mut cache = cache.lock().unwrap();
+            let cached = cache.repos.get_mut(&git_dir)?;
+            if cached.options != request.options {
+                cached.options = request.options.clone();
+                cached.invalidate();
+            }
+            if let Some(info) = &cached.info {
+                return Some(info.clone());
+            }
+            (cached.status_repo.clone(), cached.generation)
+        };
+
+        let info = {
+            let mut repo = status_repo.lock().unwrap();
+            let mut info = GitInfo::read_without_status(&mut repo);
+            info.set_status(&repo, &request.options);
+            info
+        };
+
+        let mut cache = cache.lock().unwrap();
+        if let Some(cached) = cache.repos.get_mut(&git_dir) {
+            if cached.watched && cached.generation == generation {
+                cached.info = Some(info.clone());
+            }
+        }
+        Some(info)
+    }
+
+    impl Cache {
+        fn handle_change(&mut self, watches: &mut Watches, event: inotify::Event<&OsStr>) {
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                // Events were lost, so any repository could have changed
+                self.repos.values_mut().for_each(CachedRepo::invalidate);
+                return;
+            }
+            // Git writes to a lock file and then renames it over the real
+            // one, so only the rename matters
+            if event.name.map_or(false, |x| {
+                Path::new(x).extension() == Some(OsStr::new("lock"))
+            }) {
+                return;
+            }
+
+            let Some(watch) = self.watches.get(&event.wd) else {
+                return;
+            };
+            let git_dir = watch.git_dir.clone();
+            let new_dir = match event.name {
+                Some(name) if watch.recursive && event.mask.contains(EventMask::ISDIR) => {
+                    Some(watch.dir.join(name))
+                }
+                _ => None,
+            };
+            if event.mask.contains(EventMask::IGNORED) {
+                self.watches.remove(&event.wd);
+            }
+
+            let Some(cached) = self.repos.get_mut(&git_dir) else {
+                return;
+            };
+            cached.invalidate();
+            if let Some(dir) = new_dir.filter(|x| x.is_dir()) {
+                if event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                    && dir.file_name() != Some(OsStr::new(".git"))
+                    && !is_ignored(&cached.repo, &dir)
+                    && watch_dir(
+                        &cached.repo,
+                        watches,
+                        &mut self.watches,
+                        &git_dir,
+                        &dir,
+                        true,
+                    )
+                    .is_err()
+                {
+                    // Nothing will be cached, so the watches are no use
+                    cached.watched = false;
+                    unwatch_repository(watches, &mut self.watches, &git_dir);
+                }
+            }
+        }
+    }
+
+    fn watch_for_changes(mut inotify: Inotify, cache: Arc<Mutex<Cache>>) {
+        let mut watches = inotify.watches();
+        let mut buffer = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(_) => continue,
+            };
+            let mut cache = cache.lock().unwrap();
+            for event in events {
+                cache.handle_change(&mut watches, event);
+            }
+        }
+    }
+
+    fn handle_client(
+        mut stream: UnixStream,
+        cache: &Mutex<Cache>,
+        watches: &mut Watches,
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut request = String::new();
+        stream.read_to_string(&mut request)?;
+        let request: Request =
+            toml::from_str(&request).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let response = Response {
+            info: get_info(cache, watches, request),
+        };
+        let response = toml::to_string(&response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        stream.write_all(response.as_bytes())
+    }
+
+    /// Serves prompts until killed, each on its own thread
+    pub fn run_daemon() -> io::Result<()> {
+        let dir = socket_dir();
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        if !is_private(&dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} must be a directory that only you can write to",
+                    dir.display()
+                ),
+            ));
+        }
+
+        let path = socket_path();
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", path.display()),
+            ));
+        }
+        // Left behind by a daemon that was killed
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        let inotify = Inotify::init()?;
+        let watches = inotify.watches();
+        let cache = Arc::new(Mutex::new(Cache::default()));
+        {
+            let cache = cache.clone();
+            thread::spawn(move || watch_for_changes(inotify, cache));
+        }
+
+        for stream in listener.incoming().flatten() {
+            let cache = cache.clone();
+            let mut watches = watches.clone();
+            thread::spawn(move || handle_client(stream, &cache, &mut watches));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::{symlink, PermissionsExt},
+        path::PathBuf,
+    };
+
+    use crate::config::GitConfig;
+
+    use super::{is_private, Request, Response};
+
+    #[test]
+    fn request_round_trip() {
+        let request = Request {
+            path: PathBuf::from("/home/user/project"),
+            options: GitConfig {
+                include_untracked: false,
+                ..Default::default()
+            },
+        };
+        let parsed: Request = toml::from_str(&toml::to_string(&request).unwrap()).unwrap();
+        assert_eq!(parsed.path, request.path);
+        assert_eq!(parsed.options, request.options);
+    }
+
+    #[test]
+    fn empty_response_means_no_repository() {
+        let response = toml::to_string(&Response::default()).unwrap();
+        assert!(toml::from_str::<Response>(&response)
+            .unwrap()
+            .info
+            .is_none());
+    }
+
+    #[test]
+    fn socket_dir_must_be_private() {
+        let dir = std::env::temp_dir().join(format!("rps-daemon-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let private = is_private(&dir);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let writable = is_private(&dir);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        symlink(&dir, dir.join("link")).unwrap();
+        let link = is_private(&dir.join("link"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(private);
+        assert!(!writable);
+        assert!(!link);
+        assert!(!is_private(&dir));
+    }
+}
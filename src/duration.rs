@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use crate::{
+    colors,
+    segments::{Context, PromptSegment, RenderedSegment, ShrinkPriority},
+    width::width,
+};
+
+/// How long the last command took to run
+pub struct CmdDurationSegment {
+    full: String,
+    short: String,
+}
+
+const UNITS: [(u64, char); 4] = [(86_400, 'd'), (3_600, 'h'), (60, 'm'), (1, 's')];
+
+/// Every unit down to seconds, like `1h 0m 12s`, or with tenths of a second
+/// under a minute, like `4.2s`
+fn format_full(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        return format!("{}.{}s", seconds, duration.subsec_millis() / 100);
+    }
+
+    let mut parts = Vec::new();
+    let mut remaining = seconds;
+    for (size, unit) in UNITS {
+        if parts.is_empty() && remaining < size {
+            continue;
+        }
+        parts.push(format!("{}{}", remaining / size, unit));
+        remaining %= size;
+    }
+    parts.join(" ")
+}
+
+/// Only the largest unit, like `1h` or `4s`
+fn format_short(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (size, unit) = UNITS
+        .into_iter()
+        .find(|(size, _)| seconds >= *size)
+        .unwrap_or(UNITS[3]);
+    format!("{}{}", seconds / size, unit)
+}
+
+impl CmdDurationSegment {
+    pub fn new(context: &Context) -> Option<Self> {
+        let duration = context.duration?;
+        if duration < Duration::from_millis(context.config.duration.min_duration_ms) {
+            return None;
+        }
+        Some(CmdDurationSegment::new_from_duration(duration))
+    }
+
+    fn new_from_duration(duration: Duration) -> Self {
+        CmdDurationSegment {
+            full: format_full(duration),
+            short: format_short(duration),
+        }
+    }
+}
+
+impl PromptSegment for CmdDurationSegment {
+    fn get_base_width(&self, shrink: ShrinkPriority) -> usize {
+        match shrink {
+            ShrinkPriority::Unconstrained => width(&self.full) + 2,
+            ShrinkPriority::ShrinkComfortable => width(&self.short) + 2,
+            ShrinkPriority::ShrinkBeyondMin => 0,
+        }
+    }
+
+    fn get_actual_width_when_under(&self, max_size: usize) -> usize {
+        if max_size >= width(&self.full) + 2 {
+            width(&self.full) + 2
+        } else if max_size >= width(&self.short) + 2 {
+            width(&self.short) + 2
+        } else {
+            0
+        }
+    }
+
+    fn render_at_size(&self, max_size: usize) -> RenderedSegment {
+        let text = if max_size >= width(&self.full) + 2 {
+            format!(" {} ", self.full)
+        } else if max_size >= width(&self.short) + 2 {
+            format!(" {} ", self.short)
+        } else {
+            String::new()
+        };
+
+        RenderedSegment {
+            text,
+            bg_color: colors::BLACK,
+            fg_color: colors::CYAN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::segments::{Context, PromptSegment, ShrinkPriority};
+
+    use super::{format_full, format_short, CmdDurationSegment};
+
+    #[test]
+    fn format_units() {
+        assert_eq!(format_full(Duration::from_millis(4_250)), "4.2s");
+        assert_eq!(format_short(Duration::from_millis(4_250)), "4s");
+        assert_eq!(format_full(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(format_short(Duration::from_secs(125)), "2m");
+        assert_eq!(format_full(Duration::from_secs(3_612)), "1h 0m 12s");
+        assert_eq!(format_short(Duration::from_secs(3_612)), "1h");
+        assert_eq!(format_full(Duration::from_secs(90_061)), "1d 1h 1m 1s");
+        assert_eq!(format_short(Duration::from_millis(300)), "0s");
+    }
+
+    #[test]
+    fn hidden_under_threshold() {
+        let mut context = Context {
+            duration: Some(Duration::from_millis(1_500)),
+            ..Context::for_test()
+        };
+        assert!(CmdDurationSegment::new(&context).is_none());
+        context.config.duration.min_duration_ms = 1_000;
+        assert!(CmdDurationSegment::new(&context).is_some());
+        context.duration = None;
+        assert!(CmdDurationSegment::new(&context).is_none());
+    }
+
+    #[test]
+    fn shortens_when_constrained() {
+        let segment = CmdDurationSegment::new_from_duration(Duration::from_secs(3_612));
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 11);
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 4);
+        assert_eq!(segment.render_at_size(20).text, " 1h 0m 12s ");
+        assert_eq!(segment.get_actual_width_when_under(10), 4);
+        assert_eq!(segment.render_at_size(10).text, " 1h ");
+        assert_eq!(segment.render_at_size(3).text, "");
+    }
+}
@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+
+use crate::Shell;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// Marks up prompt text so that the shell's line editor can tell which bytes
+/// take up space on screen and which are zero-width escape sequences.
+pub trait EscapeBackend {
+    /// Wraps a sequence that does not advance the cursor
+    fn non_printing(&self, sequence: &str) -> String;
+
+    /// Escapes printable text so the shell shows it literally
+    fn printable<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(text)
+    }
+
+    /// Escapes a whole string, wrapping every escape sequence found in it
+    fn escape(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(ESC) {
+            result.push_str(&self.printable(&rest[..start]));
+            let len = escape_sequence_len(&rest[start..]);
+            result.push_str(&self.non_printing(&rest[start..start + len]));
+            rest = &rest[start + len..];
+        }
+        result.push_str(&self.printable(rest));
+        result
+    }
+}
+
+/// zsh wraps non-printing sequences in `%{ %}` and uses `%` for its own
+/// prompt escapes
+pub struct ZshEscape;
+
+/// bash wraps non-printing sequences in `\[ \]`, and with `promptvars` on it
+/// expands `$` and backticks in the prompt and reads `\` as a prompt escape
+pub struct BashEscape;
+
+/// fish, nushell, pwsh and elvish measure escape sequences themselves, so they
+/// are passed through as-is
+pub struct FishEscape;
+
+/// xonsh's prompt_toolkit and readline backends both skip text between `\x01`
+/// and `\x02`, and braces are format fields in its prompts
+pub struct XonshEscape;
+
+impl EscapeBackend for ZshEscape {
+    /// zsh still expands `%` escapes inside `%{ %}`
+    fn non_printing(&self, sequence: &str) -> String {
+        format!("%{{{}%}}", sequence.replace('%', "%%"))
+    }
+
+    fn printable<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if text.contains('%') {
+            Cow::Owned(text.replace('%', "%%"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+impl EscapeBackend for BashEscape {
+    /// bash would read the `ESC \` string terminator as an escaped backslash
+    /// and lose the closing `\]`, so it is replaced by BEL
+    fn non_printing(&self, sequence: &str) -> String {
+        let sequence = sequence.replace("\x1b\\", "\x07");
+        format!("\\[{}\\]", self.printable(&sequence))
+    }
+
+    /// `\$` is shown as `#` for root, but is the only form bash doesn't expand
+    fn printable<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !text.contains(['\\', '$', '`']) {
+            return Cow::Borrowed(text);
+        }
+        let mut result = String::with_capacity(text.len() + 2);
+        for c in text.chars() {
+            if matches!(c, '\\' | '$' | '`') {
+                result.push('\\');
+            }
+            result.push(c);
+        }
+        Cow::Owned(result)
+    }
+}
+
+impl EscapeBackend for FishEscape {
+    fn non_printing(&self, sequence: &str) -> String {
+        sequence.to_string()
+    }
+}
+
+impl EscapeBackend for XonshEscape {
+    fn non_printing(&self, sequence: &str) -> String {
+        format!("\x01{}\x02", sequence)
+    }
+
+    fn printable<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if text.contains(['{', '}']) {
+            Cow::Owned(text.replace('{', "{{").replace('}', "}}"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+pub fn get_escape_backend(shell: Option<&Shell>) -> &'static dyn EscapeBackend {
+    match shell {
+        Some(Shell::Zsh) => &ZshEscape,
+        Some(Shell::Bash) => &BashEscape,
+        Some(Shell::Xonsh) => &XonshEscape,
+        Some(Shell::Fish | Shell::Nu | Shell::Pwsh | Shell::Elvish) | None => &FishEscape,
+    }
+}
+
+/// `text` without any escape sequences, which is what takes up space on
+/// screen
+pub fn strip_escapes(text: &str) -> Cow<str> {
+    if !text.contains(ESC) {
+        return Cow::Borrowed(text);
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(ESC) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + escape_sequence_len(&rest[start..])..];
+    }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+/// Length in bytes of the escape sequence at the start of `text`, which must
+/// begin with ESC. Handles CSI (`ESC [ ... final`), OSC (`ESC ] ... BEL` or
+/// `ESC ] ... ESC \`) and two-byte sequences.
+fn escape_sequence_len(text: &str) -> usize {
+    debug_assert!(text.starts_with(ESC));
+    let mut chars = text.char_indices().skip(1);
+    match chars.next() {
+        None => 1,
+        Some((_, '[')) => chars
+            .find(|(_, c)| ('\x40'..='\x7e').contains(c))
+            .map_or(text.len(), |(i, c)| i + c.len_utf8()),
+        Some((_, ']')) => {
+            let mut previous = None;
+            for (i, c) in chars {
+                if c == BEL {
+                    return i + 1;
+                }
+                if previous == Some(ESC) && c == '\\' {
+                    return i + 1;
+                }
+                previous = Some(c);
+            }
+            text.len()
+        }
+        Some((i, c)) => i + c.len_utf8(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_escapes, BashEscape, EscapeBackend, FishEscape, XonshEscape, ZshEscape};
+
+    const COLORED: &str = "\x1b[30m\x1b[44m ~ \x1b[0m";
+
+    #[test]
+    fn zsh_wraps_sequences() {
+        assert_eq!(
+            ZshEscape.escape(COLORED),
+            "%{\x1b[30m%}%{\x1b[44m%} ~ %{\x1b[0m%}"
+        );
+    }
+
+    #[test]
+    fn zsh_escapes_percent() {
+        assert_eq!(ZshEscape.escape("100%"), "100%%");
+        assert_eq!(
+            ZshEscape.escape("\x1b[31m%\x1b[0m"),
+            "%{\x1b[31m%}%%%{\x1b[0m%}"
+        );
+    }
+
+    #[test]
+    fn bash_wraps_sequences() {
+        assert_eq!(
+            BashEscape.escape(COLORED),
+            "\\[\x1b[30m\\]\\[\x1b[44m\\] ~ \\[\x1b[0m\\]"
+        );
+    }
+
+    #[test]
+    fn fish_passes_through() {
+        assert_eq!(FishEscape.escape(COLORED), COLORED);
+        assert_eq!(FishEscape.escape("100%"), "100%");
+    }
+
+    #[test]
+    fn xonsh_wraps_sequences_and_escapes_braces() {
+        assert_eq!(
+            XonshEscape.escape(COLORED),
+            "\x01\x1b[30m\x02\x01\x1b[44m\x02 ~ \x01\x1b[0m\x02"
+        );
+        assert_eq!(XonshEscape.escape("{user}"), "{{user}}");
+    }
+
+    #[test]
+    fn wraps_osc_sequences() {
+        let link = "\x1b]8;;file:///tmp\x1b\\tmp\x1b]8;;\x07";
+        assert_eq!(
+            BashEscape.escape(link),
+            "\\[\x1b]8;;file:///tmp\x07\\]tmp\\[\x1b]8;;\x07\\]"
+        );
+    }
+
+    #[test]
+    fn bash_escapes_expansions() {
+        assert_eq!(
+            BashEscape.escape("$(touch x) `id` \\u"),
+            "\\$(touch x) \\`id\\` \\\\u"
+        );
+        assert_eq!(
+            BashEscape.escape("\x1b[31m$HOME\x1b[0m"),
+            "\\[\x1b[31m\\]\\$HOME\\[\x1b[0m\\]"
+        );
+    }
+
+    #[test]
+    fn zsh_escapes_percent_in_links() {
+        let link = "\x1b]8;;file:///my%20files\x07my files\x1b]8;;\x07";
+        assert_eq!(
+            ZshEscape.escape(link),
+            "%{\x1b]8;;file:///my%%20files\x07%}my files%{\x1b]8;;\x07%}"
+        );
+    }
+
+    #[test]
+    fn strips_sequences() {
+        assert_eq!(strip_escapes(COLORED), " ~ ");
+        assert_eq!(
+            strip_escapes("\x1b]8;;file:///tmp\x07tmp\x1b]8;;\x07 "),
+            "tmp "
+        );
+        assert_eq!(strip_escapes("plain"), "plain");
+    }
+
+    #[test]
+    fn plain_text_unchanged() {
+        assert_eq!(BashEscape.escape(" ✓ ✘ "), " ✓ ✘ ");
+        assert_eq!(ZshEscape.escape(""), "");
+    }
+}
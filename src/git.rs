@@ -1,24 +1,54 @@
-use std::cmp::min;
+use std::{cmp::min, path::Path, sync::mpsc, thread, time::Duration};
 
-use crate::{colors, segments::*};
-use git2::{Repository, RepositoryOpenFlags};
-use unicode_segmentation::UnicodeSegmentation;
+use crate::{
+    colors,
+    config::GitConfig,
+    daemon::{self, QueryError},
+    hyperlink::{hyperlink, percent_encode},
+    segments::*,
+    width::{take_width, width},
+};
+use git2::{Branch, Repository, RepositoryOpenFlags, StatusOptions};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// Number of files in each state. A file can be in more than one, such as
+/// when it has both staged and unstaged changes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 struct FileChanges {
-    staged: bool,
-    unstaged: bool,
-    conflicted: bool,
+    staged: usize,
+    unstaged: usize,
+    conflicted: usize,
+    untracked: usize,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum GitStatus {
     Clean,
-    UntrackedFiles,
     Changes(FileChanges),
+    /// The status took too long to compute
+    Unknown,
 }
 
-#[derive(Debug)]
+/// Commits that the local branch and its upstream don't have in common
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Upstream {
+    ahead: usize,
+    behind: usize,
+}
+
+impl std::fmt::Display for Upstream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ahead != 0 {
+            write!(f, "{}{}", AHEAD_SYMBOL, self.ahead)?;
+        }
+        if self.behind != 0 {
+            write!(f, "{}{}", BEHIND_SYMBOL, self.behind)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum GitState {
     Clean,
     Bisect,
@@ -27,18 +57,48 @@ enum GitState {
     Cherrypick,
 }
 
+/// How much of the status symbols to show, from most to least detailed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StatusDetail {
+    Full,
+    /// Without upstream counts
+    Local,
+    /// Without upstream counts, and the stash symbol without its count
+    Compact,
+}
+
+/// Everything the segment shows about a repository, which the daemon caches
+/// and sends to the prompt
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitInfo {
+    status: GitStatus,
+    mode: GitState,
+    upstream: Option<Upstream>,
+    stashes: usize,
+    branch_name: String,
+    /// The branch's page on the website hosting `origin`
+    branch_url: Option<String>,
+}
+
 pub struct GitSegment {
     status: GitStatus,
     mode: GitState,
-    status_str_len: usize,
+    upstream: Option<Upstream>,
+    stashes: usize,
     branch_name: String,
     branch_name_len: usize,
+    branch_url: Option<String>,
+    options: GitConfig,
 }
 
-const MIN_BRANCH_TEXT: usize = 4;
 const UNSTAGED_CHANGES_SYMBOL: char = '\u{25CF}';
 const STAGED_CHANGES_SYMBOL: char = '\u{271A}';
 const CONFLICT_SYMBOL: char = '\u{26A0}';
+const UNTRACKED_SYMBOL: char = '?';
+const STATUS_UNKNOWN_SYMBOL: char = '\u{29D6}';
+const AHEAD_SYMBOL: char = '\u{2191}';
+const BEHIND_SYMBOL: char = '\u{2193}';
+const STASH_SYMBOL: char = '\u{2691}';
 
 fn get_branch_name(repo: &Repository) -> Option<String> {
     if repo.head_detached().ok().unwrap_or(false) {
@@ -50,66 +110,267 @@ fn get_branch_name(repo: &Repository) -> Option<String> {
     head.shorthand().map(|x| x.to_string())
 }
 
+/// The website for a remote URL like `git@github.com:me/rps.git`,
+/// `ssh://git@host:22/me/rps` or `https://host/me/rps.git`
+fn get_web_url(remote: &str) -> Option<String> {
+    let remote = remote.trim_end_matches('/');
+    let remote = remote.strip_suffix(".git").unwrap_or(remote);
+    let (scheme, host, path) = match remote.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            let host = authority.rsplit_once('@').map_or(authority, |x| x.1);
+            match scheme {
+                "http" | "https" => (scheme, host, path),
+                // The SSH port has nothing to do with the website's
+                _ => ("https", host.split(':').next()?, path),
+            }
+        }
+        None => {
+            let (authority, path) = remote.split_once(':')?;
+            let host = authority.rsplit_once('@').map_or(authority, |x| x.1);
+            ("https", host, path.trim_start_matches('/'))
+        }
+    };
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+    Some(format!("{}://{}/{}", scheme, host, path))
+}
+
+/// The page for `branch` on GitHub, GitLab or Gitea, which are told apart by
+/// the host name
+fn get_branch_url(remote: &str, branch: &str) -> Option<String> {
+    let web_url = get_web_url(remote)?;
+    let host = web_url.split('/').nth(2)?;
+    let tree = if host.contains("github") {
+        "tree"
+    } else if host.contains("gitlab") {
+        "-/tree"
+    } else if host.contains("gitea") || host.contains("codeberg") {
+        "src/branch"
+    } else {
+        return None;
+    };
+    Some(format!(
+        "{}/{}/{}",
+        web_url,
+        tree,
+        percent_encode(branch.as_bytes())
+    ))
+}
+
+fn get_origin_branch_url(repo: &Repository, branch_name: &str) -> Option<String> {
+    if !repo.head().ok()?.is_branch() {
+        return None;
+    }
+    let origin = repo.find_remote("origin").ok()?;
+    get_branch_url(origin.url()?, branch_name)
+}
+
+fn get_upstream(repo: &Repository) -> Option<Upstream> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    let local = head.target()?;
+    let upstream = Branch::wrap(head).upstream().ok()?.get().target()?;
+    let (ahead, behind) = repo.graph_ahead_behind(local, upstream).ok()?;
+    if ahead == 0 && behind == 0 {
+        return None;
+    }
+    Some(Upstream { ahead, behind })
+}
+
+fn get_status_options(options: &GitConfig) -> StatusOptions {
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(options.include_untracked)
+        .recurse_untracked_dirs(false)
+        .include_ignored(false)
+        .recurse_ignored_dirs(false)
+        .exclude_submodules(options.exclude_submodules);
+    status_options
+}
+
+fn compute_status(repo: &Repository, options: &GitConfig) -> GitStatus {
+    match repo.statuses(Some(&mut get_status_options(options))) {
+        Ok(statuses) => get_repo_status(statuses.iter().map(|x| x.status())),
+        Err(_) => GitStatus::Unknown,
+    }
+}
+
+/// Computes the status on another thread, giving up once the configured time
+/// has passed. The thread is left to finish on its own.
+fn compute_status_with_timeout(repo: &Repository, options: &GitConfig) -> GitStatus {
+    if options.status_timeout_ms == 0 {
+        return compute_status(repo, options);
+    }
+
+    let git_dir = repo.path().to_path_buf();
+    let thread_options = options.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let status = match Repository::open(git_dir) {
+            Ok(repo) => compute_status(&repo, &thread_options),
+            Err(_) => GitStatus::Unknown,
+        };
+        let _ = sender.send(status);
+    });
+
+    receiver
+        .recv_timeout(Duration::from_millis(options.status_timeout_ms))
+        .unwrap_or(GitStatus::Unknown)
+}
+
+fn count_stashes(repo: &mut Repository) -> usize {
+    let mut count = 0;
+    let _ = repo.stash_foreach(|_, _, _| {
+        count += 1;
+        true
+    });
+    count
+}
+
+/// Opens the repository containing `path`
+pub fn open_repository(path: &Path) -> Option<Repository> {
+    Repository::open_ext(
+        path,
+        RepositoryOpenFlags::empty(),
+        &[] as &[&std::ffi::OsStr],
+    )
+    .ok()
+}
+
+impl GitInfo {
+    /// Reads everything except the status, which is left unknown
+    pub fn read_without_status(repo: &mut Repository) -> GitInfo {
+        let stashes = count_stashes(repo);
+        let branch_name = get_branch_name(repo).unwrap_or(String::from("<NO HEAD>"));
+        GitInfo {
+            status: GitStatus::Unknown,
+            mode: get_repo_mode(repo),
+            upstream: get_upstream(repo),
+            stashes,
+            branch_url: get_origin_branch_url(repo, &branch_name),
+            branch_name,
+        }
+    }
+
+    pub fn set_status(&mut self, repo: &Repository, options: &GitConfig) {
+        self.status = compute_status(repo, options);
+    }
+
+    fn read(repo: &mut Repository, options: &GitConfig) -> GitInfo {
+        let mut info = GitInfo::read_without_status(repo);
+        info.status = compute_status_with_timeout(repo, options);
+        info
+    }
+}
+
 impl GitSegment {
     pub fn new(context: &Context) -> Option<Self> {
         let path = context.path.as_ref()?;
-        let repo = Repository::open_ext(
-            path,
-            RepositoryOpenFlags::empty(),
-            &[] as &[&std::ffi::OsStr],
-        )
-        .ok()?;
-        let statuses = repo.statuses(None).ok()?;
-        let status = get_repo_status(&statuses);
-        let mode = get_repo_mode(&repo);
-        let status_str_len = calculate_status_size_len(&status, &mode);
-        let branch_name = get_branch_name(&repo).unwrap_or(String::from("<NO HEAD>"));
-        let branch_name_len = branch_name.graphemes(true).count();
-        Some(GitSegment {
-            status,
-            mode,
-            status_str_len,
-            branch_name,
-            branch_name_len,
-        })
+        let options = &context.config.git;
+        let info = match daemon::query(path, options) {
+            Ok(info) => info?,
+            // The daemon is still working out the status, so don't compute
+            // it a second time here
+            Err(QueryError::Timeout) => GitInfo::read_without_status(&mut open_repository(path)?),
+            Err(QueryError::Unavailable) => GitInfo::read(&mut open_repository(path)?, options),
+        };
+        let mut segment = GitSegment::from_info(info, options.clone());
+        if !context.config.hyperlinks {
+            segment.branch_url = None;
+        }
+        Some(segment)
+    }
+
+    fn from_info(info: GitInfo, options: GitConfig) -> Self {
+        GitSegment {
+            branch_name_len: width(&info.branch_name),
+            status: info.status,
+            mode: info.mode,
+            upstream: info.upstream,
+            stashes: info.stashes,
+            branch_name: info.branch_name,
+            branch_url: info.branch_url,
+            options,
+        }
+    }
+
+    fn link_branch(&self, text: &str) -> String {
+        match &self.branch_url {
+            Some(url) => hyperlink(url, text),
+            None => text.to_string(),
+        }
     }
 
     fn get_unconstrained_total_len(&self) -> usize {
+        let status_str_len = self.calculate_status_size_len(StatusDetail::Full);
+        let mut size = self.branch_name_len + 4;
+        if status_str_len != 0 {
+            size += status_str_len + 1;
+        }
+        size
+    }
+    /// Length with the whole branch name, once the upstream counts are hidden
+    fn get_full_branch_name_len(&self) -> usize {
+        let status_str_len = self.calculate_status_size_len(StatusDetail::Local);
         let mut size = self.branch_name_len + 4;
-        if self.status_str_len != 0 {
-            size += self.status_str_len + 1;
+        if status_str_len != 0 {
+            size += status_str_len + 1;
         }
         size
     }
     fn get_min_len_with_branch_name(&self) -> usize {
-        let mut size = min(self.branch_name_len, MIN_BRANCH_TEXT + 3) + 4;
-        if self.status_str_len != 0 {
-            size += self.status_str_len + 1;
+        let status_str_len = self.calculate_status_size_len(StatusDetail::Local);
+        let mut size = min(self.branch_name_len, self.options.min_branch_text + 3) + 4;
+        if status_str_len != 0 {
+            size += status_str_len + 1;
         }
         size
     }
 
-    fn render_status_symbols(&self, string_builder: &mut String) {
-        if let GitStatus::Changes(FileChanges {
-            staged,
-            unstaged,
-            conflicted,
-        }) = self.status
-        {
-            if staged || unstaged || conflicted {
-                string_builder.push(' ');
+    fn render_status_symbols(&self, string_builder: &mut String, detail: StatusDetail) {
+        if let GitStatus::Changes(changes) = &self.status {
+            let show_counts = self.options.show_counts && detail != StatusDetail::Compact;
+            let mut symbols = String::new();
+            for (count, symbol) in [
+                (changes.unstaged, UNSTAGED_CHANGES_SYMBOL),
+                (changes.staged, STAGED_CHANGES_SYMBOL),
+                (changes.conflicted, CONFLICT_SYMBOL),
+                (changes.untracked, UNTRACKED_SYMBOL),
+            ] {
+                if count == 0 {
+                    continue;
+                }
+                symbols.push(symbol);
+                if show_counts {
+                    symbols.push_str(&count.to_string());
+                }
             }
-            if unstaged {
-                string_builder.push(UNSTAGED_CHANGES_SYMBOL);
-            }
-            if staged {
-                string_builder.push(STAGED_CHANGES_SYMBOL);
+            if !symbols.is_empty() {
+                string_builder.push(' ');
+                string_builder.push_str(&symbols);
             }
-            if conflicted {
-                string_builder.push(CONFLICT_SYMBOL);
+        } else if self.status == GitStatus::Unknown {
+            string_builder.push(' ');
+            string_builder.push(STATUS_UNKNOWN_SYMBOL);
+        }
+
+        if self.stashes != 0 {
+            string_builder.push(' ');
+            string_builder.push(STASH_SYMBOL);
+            if detail != StatusDetail::Compact {
+                string_builder.push_str(&self.stashes.to_string());
             }
         }
 
+        if let (StatusDetail::Full, Some(upstream)) = (detail, &self.upstream) {
+            string_builder.push(' ');
+            string_builder.push_str(&upstream.to_string());
+        }
+
         match self.mode {
             GitState::Clean => {}
             GitState::Bisect => string_builder.push_str(" <B>"),
@@ -118,30 +379,12 @@ impl GitSegment {
             GitState::Cherrypick => string_builder.push_str(" >C>"),
         }
     }
-}
 
-fn calculate_status_size_len(status: &GitStatus, mode: &GitState) -> usize {
-    let status_symbol_len = match status {
-        GitStatus::Clean => 0,
-        GitStatus::UntrackedFiles => 0,
-        GitStatus::Changes(FileChanges {
-            staged,
-            unstaged,
-            conflicted,
-        }) => *staged as usize + *unstaged as usize + *conflicted as usize,
-    };
-    let mode_symol_len = match mode {
-        GitState::Clean => 0,
-        GitState::Bisect => 3,
-        GitState::Rebase => 3,
-        GitState::Merge => 3,
-        GitState::Cherrypick => 3,
-    };
-    match (status_symbol_len, mode_symol_len) {
-        (0, 0) => 0,
-        (x, 0) => x,
-        (0, y) => y,
-        (x, y) => x + y + 1,
+    /// Width of the status symbols, not counting the space before them
+    fn calculate_status_size_len(&self, detail: StatusDetail) -> usize {
+        let mut string_builder = String::new();
+        self.render_status_symbols(&mut string_builder, detail);
+        width(&string_builder).saturating_sub(1)
     }
 }
 
@@ -162,19 +405,16 @@ fn get_repo_mode(repo: &Repository) -> GitState {
     }
 }
 
-fn get_repo_status(statuses: &git2::Statuses) -> GitStatus {
-    let mut unstaged_changes = false;
-    let mut staged_changes = false;
-    let mut conflicted = false;
-    let mut untracked = false;
-    for e in statuses.iter() {
-        let status = e.status();
-        if status.is_empty() {
+fn get_repo_status(statuses: impl Iterator<Item = git2::Status>) -> GitStatus {
+    let mut changes = FileChanges::default();
+    for status in statuses {
+        if status.is_conflicted() {
+            changes.conflicted += 1;
             continue;
         }
+
         if status.is_wt_new() {
-            untracked = true;
-            continue;
+            changes.untracked += 1;
         }
 
         if status.is_wt_modified()
@@ -182,8 +422,7 @@ fn get_repo_status(statuses: &git2::Statuses) -> GitStatus {
             || status.is_wt_typechange()
             || status.is_wt_renamed()
         {
-            unstaged_changes = true;
-            continue;
+            changes.unstaged += 1;
         }
 
         if status.is_index_new()
@@ -192,26 +431,14 @@ fn get_repo_status(statuses: &git2::Statuses) -> GitStatus {
             || status.is_index_typechange()
             || status.is_index_renamed()
         {
-            staged_changes = true;
-            continue;
-        }
-
-        if status.is_conflicted() {
-            conflicted = true;
-            continue;
+            changes.staged += 1;
         }
     }
 
-    if staged_changes || unstaged_changes || conflicted {
-        GitStatus::Changes(FileChanges {
-            staged: staged_changes,
-            unstaged: unstaged_changes,
-            conflicted,
-        })
-    } else if untracked {
-        GitStatus::UntrackedFiles
-    } else {
+    if changes == FileChanges::default() {
         GitStatus::Clean
+    } else {
+        GitStatus::Changes(changes)
     }
 }
 
@@ -225,12 +452,16 @@ impl PromptSegment for GitSegment {
     }
 
     fn get_actual_width_when_under(&self, max_size: usize) -> usize {
-        if max_size >= self.get_min_len_with_branch_name() {
-            min(max_size, self.get_unconstrained_total_len())
+        if max_size >= self.get_unconstrained_total_len() {
+            self.get_unconstrained_total_len()
+        } else if max_size >= self.get_full_branch_name_len() {
+            self.get_full_branch_name_len()
         } else if max_size >= self.get_min_len_with_branch_name() {
             max_size
-        } else if self.status_str_len != 0 && max_size >= self.status_str_len + 4 {
-            self.status_str_len + 4
+        } else if self.calculate_status_size_len(StatusDetail::Compact) != 0
+            && max_size >= self.calculate_status_size_len(StatusDetail::Compact) + 4
+        {
+            self.calculate_status_size_len(StatusDetail::Compact) + 4
         } else if max_size >= 3 {
             3
         } else {
@@ -242,33 +473,42 @@ impl PromptSegment for GitSegment {
         let text = if max_size >= self.get_unconstrained_total_len() {
             // unconstrained
             let mut string_builder = String::from(" \u{e0a0} ");
-            string_builder.push_str(self.branch_name.as_str());
-            self.render_status_symbols(&mut string_builder);
+            string_builder.push_str(&self.link_branch(&self.branch_name));
+            self.render_status_symbols(&mut string_builder, StatusDetail::Full);
+            string_builder.push(' ');
+            string_builder
+        } else if max_size >= self.get_full_branch_name_len() {
+            // hide upstream counts
+            let mut string_builder = String::from(" \u{e0a0} ");
+            string_builder.push_str(&self.link_branch(&self.branch_name));
+            self.render_status_symbols(&mut string_builder, StatusDetail::Local);
             string_builder.push(' ');
             string_builder
         } else if max_size >= self.get_min_len_with_branch_name() {
             // elipsize branch name
+            let status_str_len = self.calculate_status_size_len(StatusDetail::Local);
             let mut string_builder = String::from(" \u{e0a0} ");
-            self.branch_name
-                .graphemes(true)
-                .take(max_size.saturating_sub(
-                    3 + 3
-                        + 1
-                        + if self.status_str_len == 0 {
-                            0
-                        } else {
-                            self.status_str_len + 1
-                        },
-                ))
-                .for_each(|x| string_builder.push_str(x));
-            string_builder.push_str("...");
-            self.render_status_symbols(&mut string_builder);
+            let available = max_size.saturating_sub(
+                3 + 3
+                    + 1
+                    + if status_str_len == 0 {
+                        0
+                    } else {
+                        status_str_len + 1
+                    },
+            );
+            let kept = take_width(&self.branch_name, available);
+            string_builder.push_str(&self.link_branch(&format!("{}...", kept)));
+            // A wide character that doesn't fit is replaced by padding, which
+            // isn't part of the link
+            string_builder.push_str(&" ".repeat(available - width(kept)));
+            self.render_status_symbols(&mut string_builder, StatusDetail::Local);
             string_builder.push(' ');
             string_builder
-        } else if max_size >= self.status_str_len + 4 {
+        } else if max_size >= self.calculate_status_size_len(StatusDetail::Compact) + 4 {
             // just git symbol and status symbols
             let mut string_builder = String::from(" \u{e0a0}");
-            self.render_status_symbols(&mut string_builder);
+            self.render_status_symbols(&mut string_builder, StatusDetail::Compact);
             string_builder.push(' ');
             string_builder
         } else if max_size >= 3 {
@@ -278,15 +518,13 @@ impl PromptSegment for GitSegment {
             String::new()
         };
 
-        debug_assert_eq!(
-            text.graphemes(true).count(),
-            self.get_actual_width_when_under(max_size)
-        );
+        debug_assert_eq!(width(&text), self.get_actual_width_when_under(max_size));
         RenderedSegment {
             text,
             bg_color: match self.status {
                 GitStatus::Clean => colors::GREEN,
-                _ => colors::YELLOW,
+                GitStatus::Changes(_) => colors::YELLOW,
+                GitStatus::Unknown => colors::WHITE,
             },
             fg_color: colors::BLACK,
         }
@@ -295,27 +533,39 @@ impl PromptSegment for GitSegment {
 #[cfg(test)]
 mod tests {
     use crate::{
+        colors,
+        config::GitConfig,
         git::GitState,
         segments::{PromptSegment, ShrinkPriority},
+        width::width,
     };
 
-    use super::{calculate_status_size_len, FileChanges, GitSegment, GitStatus};
+    use git2::Status;
+    use proptest::prelude::*;
+
+    use super::{
+        get_branch_url, get_repo_status, get_web_url, FileChanges, GitInfo, GitSegment, GitStatus,
+        StatusDetail, Upstream,
+    };
 
     #[test]
     fn format_with_status() {
         let status = GitStatus::Changes(FileChanges {
-            staged: true,
-            unstaged: true,
-            conflicted: false,
+            staged: 1,
+            unstaged: 1,
+            conflicted: 0,
+            untracked: 0,
         });
         let mode = GitState::Clean;
-        let status_str_len = calculate_status_size_len(&status, &mode);
         let segment = GitSegment {
             status,
             mode,
-            status_str_len,
+            upstream: None,
+            stashes: 0,
             branch_name: "example123".to_string(),
             branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
         };
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 17);
         assert_eq!(
@@ -334,18 +584,21 @@ mod tests {
     #[test]
     fn format_no_status() {
         let status = GitStatus::Changes(FileChanges {
-            staged: false,
-            unstaged: false,
-            conflicted: false,
+            staged: 0,
+            unstaged: 0,
+            conflicted: 0,
+            untracked: 0,
         });
         let mode = GitState::Clean;
-        let status_str_len = calculate_status_size_len(&status, &mode);
         let segment = GitSegment {
             status,
             mode,
-            status_str_len,
+            upstream: None,
+            stashes: 0,
             branch_name: "example123".to_string(),
             branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
         };
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 14);
         assert_eq!(
@@ -363,18 +616,21 @@ mod tests {
     #[test]
     fn format_with_status_and_mode() {
         let status = GitStatus::Changes(FileChanges {
-            staged: true,
-            unstaged: true,
-            conflicted: false,
+            staged: 1,
+            unstaged: 1,
+            conflicted: 0,
+            untracked: 0,
         });
         let mode = GitState::Rebase;
-        let status_str_len = calculate_status_size_len(&status, &mode);
         let segment = GitSegment {
             status,
             mode,
-            status_str_len,
+            upstream: None,
+            stashes: 0,
             branch_name: "example123".to_string(),
             branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
         };
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 21);
         assert_eq!(
@@ -399,18 +655,21 @@ mod tests {
     #[test]
     fn format_no_status_with_mode() {
         let status = GitStatus::Changes(FileChanges {
-            staged: false,
-            unstaged: false,
-            conflicted: false,
+            staged: 0,
+            unstaged: 0,
+            conflicted: 0,
+            untracked: 0,
         });
         let mode = GitState::Merge;
-        let status_str_len = calculate_status_size_len(&status, &mode);
         let segment = GitSegment {
             status,
             mode,
-            status_str_len,
+            upstream: None,
+            stashes: 0,
             branch_name: "example123".to_string(),
             branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
         };
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 18);
         assert_eq!(
@@ -425,4 +684,425 @@ mod tests {
         assert_eq!(segment.render_at_size(4).text, " \u{e0a0} ");
         assert_eq!(segment.render_at_size(2).text, "");
     }
+
+    #[test]
+    fn format_with_upstream() {
+        let status = GitStatus::Changes(FileChanges {
+            staged: 0,
+            unstaged: 1,
+            conflicted: 0,
+            untracked: 0,
+        });
+        let mode = GitState::Clean;
+        let upstream = Upstream {
+            ahead: 2,
+            behind: 1,
+        };
+        let segment = GitSegment {
+            status,
+            mode,
+            upstream: Some(upstream),
+            stashes: 0,
+            branch_name: "example123".to_string(),
+            branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(segment.calculate_status_size_len(StatusDetail::Full), 6);
+        assert_eq!(segment.calculate_status_size_len(StatusDetail::Local), 1);
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 21);
+        assert_eq!(
+            segment.get_base_width(ShrinkPriority::ShrinkComfortable),
+            13
+        );
+
+        assert_eq!(
+            segment.render_at_size(40).text,
+            " \u{e0a0} example123 ● ↑2↓1 "
+        );
+        assert_eq!(segment.get_actual_width_when_under(20), 16);
+        assert_eq!(segment.render_at_size(20).text, " \u{e0a0} example123 ● ");
+        assert_eq!(segment.render_at_size(14).text, " \u{e0a0} examp... ● ");
+        assert_eq!(segment.render_at_size(12).text, " \u{e0a0} ● ");
+    }
+
+    #[test]
+    fn format_upstream_with_mode() {
+        let status = GitStatus::Clean;
+        let mode = GitState::Merge;
+        let upstream = Upstream {
+            ahead: 0,
+            behind: 12,
+        };
+        let segment = GitSegment {
+            status,
+            mode,
+            upstream: Some(upstream),
+            stashes: 0,
+            branch_name: "main".to_string(),
+            branch_name_len: 4,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(segment.render_at_size(40).text, " \u{e0a0} main ↓12 >M< ");
+        assert_eq!(segment.render_at_size(14).text, " \u{e0a0} main >M< ");
+    }
+
+    #[test]
+    fn format_with_stash() {
+        let segment = GitSegment {
+            status: GitStatus::Changes(FileChanges {
+                staged: 1,
+                unstaged: 0,
+                conflicted: 0,
+                untracked: 0,
+            }),
+            mode: GitState::Clean,
+            upstream: Some(Upstream {
+                ahead: 1,
+                behind: 0,
+            }),
+            stashes: 3,
+            branch_name: "example123".to_string(),
+            branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 22);
+        assert_eq!(
+            segment.get_base_width(ShrinkPriority::ShrinkComfortable),
+            16
+        );
+
+        assert_eq!(
+            segment.render_at_size(40).text,
+            " \u{e0a0} example123 ✚ ⚑3 ↑1 "
+        );
+        assert_eq!(
+            segment.render_at_size(21).text,
+            " \u{e0a0} example123 ✚ ⚑3 "
+        );
+        assert_eq!(segment.render_at_size(16).text, " \u{e0a0} exam... ✚ ⚑3 ");
+        assert_eq!(segment.render_at_size(15).text, " \u{e0a0} ✚ ⚑ ");
+        assert_eq!(segment.render_at_size(6).text, " \u{e0a0} ");
+    }
+
+    #[test]
+    fn format_stash_only() {
+        let segment = GitSegment {
+            status: GitStatus::Clean,
+            mode: GitState::Clean,
+            upstream: None,
+            stashes: 12,
+            branch_name: "main".to_string(),
+            branch_name_len: 4,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(segment.render_at_size(40).text, " \u{e0a0} main ⚑12 ");
+        assert_eq!(segment.render_at_size(11).text, " \u{e0a0} ⚑ ");
+        assert_eq!(segment.render_at_size(4).text, " \u{e0a0} ");
+    }
+
+    #[test]
+    fn count_staged() {
+        let status = get_repo_status(
+            [
+                Status::INDEX_NEW,
+                Status::INDEX_MODIFIED,
+                Status::INDEX_RENAMED,
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            status,
+            GitStatus::Changes(FileChanges {
+                staged: 3,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn count_unstaged() {
+        let status = get_repo_status([Status::WT_MODIFIED, Status::WT_DELETED].into_iter());
+        assert_eq!(
+            status,
+            GitStatus::Changes(FileChanges {
+                unstaged: 2,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn count_conflicted() {
+        let status = get_repo_status(
+            [Status::CONFLICTED, Status::CONFLICTED | Status::WT_MODIFIED].into_iter(),
+        );
+        assert_eq!(
+            status,
+            GitStatus::Changes(FileChanges {
+                conflicted: 2,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn count_untracked() {
+        let status = get_repo_status([Status::WT_NEW, Status::CURRENT].into_iter());
+        assert_eq!(
+            status,
+            GitStatus::Changes(FileChanges {
+                untracked: 1,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn count_file_in_several_states() {
+        let status = get_repo_status(
+            [
+                Status::INDEX_MODIFIED | Status::WT_MODIFIED,
+                Status::INDEX_NEW | Status::WT_DELETED,
+                Status::WT_MODIFIED,
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            status,
+            GitStatus::Changes(FileChanges {
+                staged: 2,
+                unstaged: 3,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn count_clean() {
+        let status = get_repo_status([Status::CURRENT, Status::IGNORED].into_iter());
+        assert_eq!(status, GitStatus::Clean);
+    }
+
+    #[test]
+    fn format_untracked() {
+        let segment = GitSegment {
+            status: GitStatus::Changes(FileChanges {
+                untracked: 4,
+                ..Default::default()
+            }),
+            mode: GitState::Clean,
+            upstream: None,
+            stashes: 0,
+            branch_name: "main".to_string(),
+            branch_name_len: 4,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(segment.render_at_size(40).text, " \u{e0a0} main ? ");
+    }
+
+    #[test]
+    fn format_with_counts() {
+        let segment = GitSegment {
+            status: GitStatus::Changes(FileChanges {
+                staged: 2,
+                unstaged: 1,
+                conflicted: 0,
+                untracked: 13,
+            }),
+            mode: GitState::Clean,
+            upstream: None,
+            stashes: 0,
+            branch_name: "example123".to_string(),
+            branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig {
+                show_counts: true,
+                ..Default::default()
+            },
+        };
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 22);
+        assert_eq!(
+            segment.render_at_size(40).text,
+            " \u{e0a0} example123 ●1✚2?13 "
+        );
+        assert_eq!(
+            segment.render_at_size(19).text,
+            " \u{e0a0} exam... ●1✚2?13 "
+        );
+        assert_eq!(segment.render_at_size(18).text, " \u{e0a0} ●✚? ");
+    }
+
+    #[test]
+    fn format_status_unknown() {
+        let segment = GitSegment {
+            status: GitStatus::Unknown,
+            mode: GitState::Clean,
+            upstream: None,
+            stashes: 0,
+            branch_name: "example123".to_string(),
+            branch_name_len: 10,
+            branch_url: None,
+            options: GitConfig::default(),
+        };
+        assert_eq!(
+            segment.render_at_size(40).text,
+            " \u{e0a0} example123 \u{29D6} "
+        );
+        assert_eq!(
+            segment.render_at_size(13).text,
+            " \u{e0a0} exam... \u{29D6} "
+        );
+        assert_eq!(segment.render_at_size(12).text, " \u{e0a0} \u{29D6} ");
+        assert_eq!(segment.render_at_size(40).bg_color, colors::WHITE);
+    }
+
+    #[test]
+    fn info_round_trip() {
+        let info = GitInfo {
+            status: GitStatus::Changes(FileChanges {
+                staged: 1,
+                untracked: 2,
+                ..Default::default()
+            }),
+            mode: GitState::Rebase,
+            upstream: Some(Upstream {
+                ahead: 0,
+                behind: 3,
+            }),
+            stashes: 1,
+            branch_name: "main".to_string(),
+            branch_url: Some("https://github.com/me/rps/tree/main".to_string()),
+        };
+        let text = toml::to_string(&info).unwrap();
+        assert_eq!(toml::from_str::<GitInfo>(&text).unwrap(), info);
+
+        let info = GitInfo {
+            status: GitStatus::Unknown,
+            upstream: None,
+            branch_url: None,
+            ..info
+        };
+        let text = toml::to_string(&info).unwrap();
+        assert_eq!(toml::from_str::<GitInfo>(&text).unwrap(), info);
+    }
+
+    #[test]
+    fn web_urls() {
+        let web = "https://github.com/me/rps";
+        assert_eq!(get_web_url("git@github.com:me/rps.git").unwrap(), web);
+        assert_eq!(get_web_url("https://github.com/me/rps.git").unwrap(), web);
+        assert_eq!(get_web_url("https://me@github.com/me/rps/").unwrap(), web);
+        assert_eq!(get_web_url("ssh://git@github.com:22/me/rps").unwrap(), web);
+        assert_eq!(
+            get_web_url("http://localhost:3000/me/rps").unwrap(),
+            "http://localhost:3000/me/rps"
+        );
+        assert_eq!(get_web_url("/srv/git/rps.git"), None);
+    }
+
+    #[test]
+    fn branch_urls() {
+        assert_eq!(
+            get_branch_url("git@github.com:me/rps.git", "feature/a b").unwrap(),
+            "https://github.com/me/rps/tree/feature/a%20b"
+        );
+        assert_eq!(
+            get_branch_url("git@gitlab.com:group/sub/rps.git", "main").unwrap(),
+            "https://gitlab.com/group/sub/rps/-/tree/main"
+        );
+        assert_eq!(
+            get_branch_url("https://codeberg.org/me/rps.git", "main").unwrap(),
+            "https://codeberg.org/me/rps/src/branch/main"
+        );
+        assert_eq!(get_branch_url("git@example.com:me/rps.git", "main"), None);
+    }
+
+    #[test]
+    fn branch_is_linked() {
+        let url = "https://github.com/me/rps/tree/example123";
+        let segment = GitSegment {
+            status: GitStatus::Clean,
+            mode: GitState::Clean,
+            upstream: None,
+            stashes: 0,
+            branch_name: "example123".to_string(),
+            branch_name_len: 10,
+            branch_url: Some(url.to_string()),
+            options: GitConfig::default(),
+        };
+        assert_eq!(
+            segment.render_at_size(40).text,
+            format!(" \u{e0a0} \x1b]8;;{}\x07example123\x1b]8;;\x07 ", url)
+        );
+        assert_eq!(
+            segment.render_at_size(11).text,
+            format!(" \u{e0a0} \x1b]8;;{}\x07exam...\x1b]8;;\x07 ", url)
+        );
+    }
+
+    #[test]
+    fn ellipsize_wide_branch_name() {
+        let segment = GitSegment::from_info(
+            GitInfo {
+                status: GitStatus::Clean,
+                mode: GitState::Clean,
+                upstream: None,
+                stashes: 0,
+                branch_name: "a日本語xyz".to_string(),
+                branch_url: None,
+            },
+            GitConfig::default(),
+        );
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 14);
+        assert_eq!(segment.render_at_size(14).text, " \u{e0a0} a日本語xyz ");
+        assert_eq!(segment.render_at_size(12).text, " \u{e0a0} a日本... ");
+        // The 本 would only half fit, so it is replaced by a space
+        assert_eq!(segment.render_at_size(11).text, " \u{e0a0} a日...  ");
+
+        // The padding comes after the link
+        let url = "https://github.com/me/rps/tree/x";
+        let linked = GitSegment {
+            branch_url: Some(url.to_string()),
+            ..segment
+        };
+        assert_eq!(
+            linked.render_at_size(11).text,
+            format!(" \u{e0a0} \x1b]8;;{}\x07a日...\x1b]8;;\x07  ", url)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn rendered_width_matches_reported(
+            branch_name in "[a-z日本🦀/]{1,15}",
+            changes in any::<bool>(),
+            max_size in 0usize..40,
+        ) {
+            let status = if changes {
+                GitStatus::Changes(FileChanges {
+                    unstaged: 2,
+                    ..Default::default()
+                })
+            } else {
+                GitStatus::Clean
+            };
+            let info = GitInfo {
+                status,
+                mode: GitState::Clean,
+                upstream: None,
+                stashes: 0,
+                branch_name,
+                branch_url: None,
+            };
+            let segment = GitSegment::from_info(info, GitConfig::default());
+            let reported = segment.get_actual_width_when_under(max_size);
+            prop_assert_eq!(width(&segment.render_at_size(max_size).text), reported);
+            prop_assert!(reported <= max_size);
+        }
+    }
 }
@@ -0,0 +1,196 @@
+use std::{ffi::CStr, path::Path};
+
+use crate::{
+    colors,
+    config::HostConfig,
+    segments::{Context, TieredSegment},
+};
+
+const CONTAINER_SYMBOL: char = '\u{2B22}';
+const HOST_SYMBOL: char = '@';
+
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+    let name = CStr::from_bytes_until_nul(&buffer).ok()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+fn username(uid: libc::uid_t) -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    let error = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if error != 0 || result.is_null() {
+        return std::env::var("USER").ok();
+    }
+    let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+fn is_container() -> bool {
+    Path::new("/run/.containerenv").exists() || Path::new("/.dockerenv").exists()
+}
+
+/// Who and where the shell is: the user unless it is the default one, the
+/// host over SSH, and a marker inside containers. Root is shown in red.
+pub struct HostSegment {
+    tiers: Vec<String>,
+    root: bool,
+}
+
+impl HostSegment {
+    pub fn new(context: &Context) -> Option<Self> {
+        let uid = unsafe { libc::getuid() };
+        let ssh =
+            std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some();
+        Self::new_from_parts(
+            username(uid),
+            hostname().filter(|_| ssh),
+            uid == 0,
+            is_container(),
+            &context.config.host,
+        )
+    }
+
+    fn new_from_parts(
+        user: Option<String>,
+        host: Option<String>,
+        root: bool,
+        container: bool,
+        options: &HostConfig,
+    ) -> Option<Self> {
+        let user = user.filter(|x| root || *x != options.default_user);
+        // Only the first part of a fully qualified name
+        let host = host.map(|x| x.split('.').next().unwrap_or_default().to_string());
+        let marker = if container {
+            format!("{} ", CONTAINER_SYMBOL)
+        } else {
+            String::new()
+        };
+
+        let mut tiers = Vec::new();
+        match (&user, &host) {
+            (Some(user), Some(host)) => {
+                tiers.push(format!(" {}{}@{} ", marker, user, host));
+                tiers.push(format!(" {}{} ", marker, host));
+            }
+            (Some(text), None) | (None, Some(text)) => {
+                tiers.push(format!(" {}{} ", marker, text));
+            }
+            (None, None) if !container => return None,
+            (None, None) => {}
+        }
+        tiers.push(if container {
+            format!(" {} ", CONTAINER_SYMBOL)
+        } else {
+            format!(" {} ", HOST_SYMBOL)
+        });
+        tiers.dedup();
+
+        Some(HostSegment { tiers, root })
+    }
+}
+
+impl TieredSegment for HostSegment {
+    fn tiers(&self) -> &[String] {
+        &self.tiers
+    }
+
+    fn colors(&self) -> (colors::Color, colors::Color) {
+        if self.root {
+            (colors::RED, colors::BLACK)
+        } else {
+            (colors::BLACK, colors::YELLOW)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        colors,
+        config::HostConfig,
+        segments::{PromptSegment, ShrinkPriority},
+    };
+
+    use super::HostSegment;
+
+    fn options(default_user: &str) -> HostConfig {
+        HostConfig {
+            default_user: default_user.to_string(),
+        }
+    }
+
+    #[test]
+    fn hides_default_user_and_local_host() {
+        let user = || Some(String::from("me"));
+        assert!(HostSegment::new_from_parts(user(), None, false, false, &options("me")).is_none());
+
+        let segment =
+            HostSegment::new_from_parts(user(), None, false, false, &options("")).unwrap();
+        assert_eq!(segment.render_at_size(10).text, " me ");
+
+        let segment = HostSegment::new_from_parts(
+            user(),
+            Some(String::from("box.example.com")),
+            false,
+            false,
+            &options("me"),
+        )
+        .unwrap();
+        assert_eq!(segment.render_at_size(10).text, " box ");
+    }
+
+    #[test]
+    fn root_is_red() {
+        let segment = HostSegment::new_from_parts(
+            Some(String::from("root")),
+            None,
+            true,
+            false,
+            &options("root"),
+        )
+        .unwrap();
+        let rendered = segment.render_at_size(10);
+        assert_eq!(rendered.text, " root ");
+        assert_eq!(rendered.bg_color, colors::RED);
+    }
+
+    #[test]
+    fn shrinks_to_host_then_icon() {
+        let segment = HostSegment::new_from_parts(
+            Some(String::from("me")),
+            Some(String::from("box")),
+            false,
+            true,
+            &options(""),
+        )
+        .unwrap();
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 10);
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 7);
+        assert_eq!(segment.render_at_size(10).text, " \u{2B22} me@box ");
+        assert_eq!(segment.get_actual_width_when_under(9), 7);
+        assert_eq!(segment.render_at_size(9).text, " \u{2B22} box ");
+        assert_eq!(segment.render_at_size(4).text, " \u{2B22} ");
+        assert_eq!(segment.get_actual_width_when_under(2), 0);
+        assert_eq!(segment.render_at_size(2).text, "");
+    }
+
+    #[test]
+    fn container_only() {
+        let segment = HostSegment::new_from_parts(None, None, false, true, &options("")).unwrap();
+        assert_eq!(segment.render_at_size(10).text, " \u{2B22} ");
+    }
+}
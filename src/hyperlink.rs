@@ -0,0 +1,57 @@
+use std::{os::unix::ffi::OsStrExt, path::Path};
+
+/// Wraps `text` in an OSC 8 hyperlink to `url`. The sequences end with BEL
+/// rather than `ESC \`, which bash would read as an escaped backslash.
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x07{}\x1b]8;;\x07", url, text)
+}
+
+/// Percent-encodes every byte except unreserved characters and `/`, which
+/// also keeps `%` and `$` out of the prompt where shells would expand them
+pub fn percent_encode(text: &[u8]) -> String {
+    let mut result = String::with_capacity(text.len());
+    for byte in text {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                result.push(*byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// A `file://` URL for an absolute path on `host`
+pub fn file_url(host: &str, path: &Path) -> String {
+    format!(
+        "file://{}{}",
+        percent_encode(host.as_bytes()),
+        percent_encode(path.as_os_str().as_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{file_url, hyperlink, percent_encode};
+
+    #[test]
+    fn encodes_reserved_characters() {
+        assert_eq!(percent_encode(b"/home/me/src"), "/home/me/src");
+        assert_eq!(percent_encode(b"a b%c$"), "a%20b%25c%24");
+        assert_eq!(percent_encode("ü".as_bytes()), "%C3%BC");
+    }
+
+    #[test]
+    fn links_files() {
+        assert_eq!(
+            file_url("box", Path::new("/home/me/my files")),
+            "file://box/home/me/my%20files"
+        );
+        assert_eq!(
+            hyperlink("file://box/tmp", "tmp"),
+            "\x1b]8;;file://box/tmp\x07tmp\x1b]8;;\x07"
+        );
+    }
+}
@@ -7,43 +7,345 @@ fn get_exe_path() -> Option<String> {
     Some(string)
 }
 
-fn init_script_zsh(exe_path: String) -> String {
-    format!(
+/// A single-quoted word for bash and zsh, which can't escape anything inside
+/// single quotes, so each `'` closes the quotes to add an escaped one
+fn posix_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Nushell's single-quoted strings have no escapes, but its double-quoted ones
+/// only need `\` and `"` escaped
+fn nu_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// PowerShell also ends single-quoted strings at typographic single quotes,
+/// and any of them is doubled to include it
+fn pwsh_quote(text: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in text.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn elvish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// With `transient`, the prompt of an accepted command line is redrawn short
+/// from a line-finish hook, which runs alongside any other plugin's
+/// zle-line-finish widget
+fn init_script_zsh(exe_path: String, transient: bool) -> String {
+    let exe_path = posix_quote(&exe_path);
+    let mut script = format!(
         "\
             unsetopt promptsubst\n\
-            precmd() {{ PS1=$({} --columns=\"$COLUMNS\" --status=\"$pipestatus\" --jobs=\"$(jobs -l | wc -l)\") }}\n\
+            zmodload zsh/datetime zsh/parameter\n\
+            autoload -Uz add-zsh-hook\n\
+            __rps_preexec() {{\n\
+                __rps_start=$EPOCHREALTIME\n\
+            }}\n\
+            __rps_precmd() {{\n\
+                local rps_status=\"$pipestatus\"\n\
+                typeset -g __rps_status=$rps_status\n\
+                local -i rps_duration=0\n\
+                if [[ -n $__rps_start ]]; then\n\
+                    rps_duration=$(( (EPOCHREALTIME - __rps_start) * 1000 ))\n\
+                    unset __rps_start\n\
+                fi\n\
+                local -a rps_named_dirs=()\n\
+                local rps_name\n\
+                for rps_name in ${{(k)nameddirs}}; do\n\
+                    rps_named_dirs+=(--named-dir=\"$rps_name=$nameddirs[$rps_name]\")\n\
+                done\n\
+                local -a prompts=(\"${{(@0)$({} --shell=zsh --columns=\"$COLUMNS\" --status=\"$rps_status\" --running-jobs=${{#${{(M)jobstates:#running:*}}}} --stopped-jobs=${{#${{(M)jobstates:#suspended:*}}}} --duration=\"$rps_duration\" \"${{rps_named_dirs[@]}}\")}}\")\n\
+                PS1=$prompts[1]\n\
+                RPROMPT=$prompts[2]\n\
+            }}\n\
+            add-zsh-hook preexec __rps_preexec\n\
+            add-zsh-hook precmd __rps_precmd\n\
         ",
         exe_path
-    )
+    );
+    if transient {
+        script.push_str(&format!(
+            "\
+                __rps_line_finish() {{\n\
+                    PS1=$({} --transient --shell=zsh --status=\"$__rps_status\")\n\
+                    RPROMPT=\n\
+                    zle reset-prompt\n\
+                }}\n\
+                autoload -Uz add-zle-hook-widget\n\
+                add-zle-hook-widget line-finish __rps_line_finish\n\
+            ",
+            exe_path
+        ));
+    }
+    script
 }
 
-fn init_script_fish(exe_path: String) -> String {
-    format!(
+/// With `transient`, fish 4.1 and later call the prompt functions again with
+/// `--final-rendering` once a command line is accepted
+fn init_script_fish(exe_path: String, transient: bool) -> String {
+    let mut script = format!(
         "\
+            function __rps_preexec --on-event fish_preexec\n\
+                set -g __rps_ran_command 1\n\
+            end\n\
             function fish_prompt\n\
-                {} --columns=\"$COLUMNS\" --status=\"$pipestatus\" --jobs=(jobs | wc -l)\n\
+                set -l last_status $pipestatus\n\
+                if contains -- --final-rendering $argv\n\
+                    {0} --transient --shell=fish --status=\"$__rps_status\"\n\
+                    return\n\
+                end\n\
+                set -g __rps_status \"$last_status\"\n\
+                set -l duration 0\n\
+                if set -q __rps_ran_command\n\
+                    set duration $CMD_DURATION\n\
+                    set -e __rps_ran_command\n\
+                end\n\
+                set -l prompts ({0} --shell=fish --columns=\"$COLUMNS\" --status=\"$last_status\" --running-jobs=(jobs | string match -r '\\trunning\\t' | count) --stopped-jobs=(jobs | string match -r '\\tstopped\\t' | count) --duration=$duration | string split0)\n\
+                set -g __rps_right_prompt $prompts[2]\n\
+                printf '%s' $prompts[1]\n\
+            end\n\
+            function fish_right_prompt\n\
+                if contains -- --final-rendering $argv\n\
+                    return\n\
+                end\n\
+                printf '%s' $__rps_right_prompt\n\
             end\n\
         ",
-        exe_path
-    )
+        fish_quote(&exe_path)
+    );
+    if transient {
+        script.push_str("set -g fish_transient_prompt 1\n");
+    }
+    script
 }
 
+/// `__rps_prompt_command` has to run before anything else in
+/// `PROMPT_COMMAND` so that `PIPESTATUS` still belongs to the user's command,
+/// and it leaves `$?` as it found it for whatever runs after it. PS0 is
+/// expanded just before a command runs, and the arithmetic inside its empty
+/// substring records the start time without printing anything. Empty command
+/// lines don't expand it, so they have no duration.
 fn init_script_bash(exe_path: String) -> String {
     format!(
-        "PROMPT_COMMAND=\"PS1=\\$({} --columns=\\\"$COLUMNS\\\" --status=\\\"${{pipestatus:-0}}\\\" --jobs=${{jobs -l | wc -l}})\"",
-        exe_path
+        r#"__rps_prompt_command() {{
+    local rps_exit=$? rps_status="${{PIPESTATUS[*]}}"
+    local rps_duration=0
+    if [[ -n $__rps_start ]]; then
+        rps_duration=$(( (${{EPOCHREALTIME/[.,]/}} - __rps_start) / 1000 ))
+        unset __rps_start
+    fi
+    local -a rps_running=($(jobs -rp)) rps_stopped=($(jobs -sp))
+    PS1=$({} --shell=bash --columns="$COLUMNS" --status="$rps_status" --running-jobs="${{#rps_running[@]}}" --stopped-jobs="${{#rps_stopped[@]}}" --duration="$rps_duration")
+    return $rps_exit
+}}
+if [[ -n $EPOCHREALTIME && $PS0 != *__rps_start* ]]; then
+    PS0="$PS0"'${{BASH:0:$((__rps_start=${{EPOCHREALTIME/[.,]/}},0))}}'
+fi
+if [[ ${{PROMPT_COMMAND[*]}} != *__rps_prompt_command* ]]; then
+    if (( BASH_VERSINFO[0] > 5 || (BASH_VERSINFO[0] == 5 && BASH_VERSINFO[1] >= 1) )); then
+        PROMPT_COMMAND=(__rps_prompt_command "${{PROMPT_COMMAND[@]}}")
+    else
+        PROMPT_COMMAND="__rps_prompt_command${{PROMPT_COMMAND:+; $PROMPT_COMMAND}}"
+    fi
+fi
+"#,
+        posix_quote(&exe_path)
     )
 }
 
-pub fn echo_init_script(shell: Shell) {
+/// The prompt is rendered in a pre_prompt hook, because changes to the
+/// environment made in the prompt closures themselves are thrown away
+fn init_script_nu(exe_path: String) -> String {
+    format!(
+        r#"$env.PROMPT_INDICATOR = ""
+$env.PROMPT_INDICATOR_VI_INSERT = ""
+$env.PROMPT_INDICATOR_VI_NORMAL = ""
+$env.PROMPT_COMMAND = {{|| $env.__RPS_PROMPTS?.0? | default "" }}
+$env.PROMPT_COMMAND_RIGHT = {{|| $env.__RPS_PROMPTS?.1? | default "" }}
+$env.config = ($env.config | upsert hooks.pre_prompt (($env.config.hooks.pre_prompt? | default []) | append {{||
+    let jobs = (try {{ job list }} catch {{ [] }})
+    let stopped = ($jobs | where type == frozen | length)
+    $env.__RPS_PROMPTS = (^{} --shell=nu $"--columns=((term size).columns)" $"--status=($env.LAST_EXIT_CODE)" $"--running-jobs=(($jobs | length) - $stopped)" $"--stopped-jobs=($stopped)" $"--duration=($env.CMD_DURATION_MS? | default 0)" | split row (char nul))
+}}))
+"#,
+        nu_quote(&exe_path)
+    )
+}
+
+fn init_script_pwsh(exe_path: String) -> String {
+    format!(
+        r#"$global:__rps_last_history_id = -1
+function global:prompt {{
+    $rps_ok = $?
+    $rps_exit_code = $global:LASTEXITCODE
+    $rps_status = if ($rps_ok) {{ 0 }} elseif ($rps_exit_code) {{ $rps_exit_code }} else {{ 1 }}
+    $rps_duration = 0
+    $rps_last = Get-History -Count 1
+    if ($rps_last -and $rps_last.Id -ne $global:__rps_last_history_id) {{
+        $global:__rps_last_history_id = $rps_last.Id
+        $rps_duration = [int]($rps_last.EndExecutionTime - $rps_last.StartExecutionTime).TotalMilliseconds
+    }}
+    $rps_jobs = @(Get-Job)
+    $rps_running = @($rps_jobs | Where-Object State -eq 'Running').Count
+    $rps_stopped = @($rps_jobs | Where-Object State -eq 'Suspended').Count
+    $rps_output = & {} --shell=pwsh "--columns=$($Host.UI.RawUI.WindowSize.Width)" "--status=$rps_status" "--running-jobs=$rps_running" "--stopped-jobs=$rps_stopped" "--duration=$rps_duration"
+    $global:LASTEXITCODE = $rps_exit_code
+    $rps_output -join "`n"
+}}
+"#,
+        pwsh_quote(&exe_path)
+    )
+}
+
+/// Elvish computes the prompt and right prompt at the same time, so rps runs
+/// once before each line is read and both just print their half. Only the
+/// `set edit:` lines at the end touch the editor, which doesn't exist outside
+/// interactive shells. `$num-bg-jobs` counts stopped jobs along with running
+/// ones and elvish has no way to tell them apart, so no job counts are passed.
+fn init_script_elvish(exe_path: String) -> String {
+    format!(
+        r#"use str
+var rps-status = 0
+var rps-duration = 0
+var rps-prompts = ['' '']
+fn rps-after-command {{|m|
+    set rps-duration = (printf '%.0f' (* $m[duration] 1000))
+    set rps-status = 0
+    if (not-eq $m[error] $nil) {{
+        set rps-status = 1
+        try {{ set rps-status = $m[error][reason][exit-status] }} catch {{ }}
+    }}
+}}
+fn rps-render {{
+    var columns = 80
+    try {{ set columns = [(str:split ' ' (stty size </dev/tty 2>/dev/null))][1] }} catch {{ }}
+    set rps-prompts = [(str:split "\x00" ({} --shell=elvish --columns=$columns --status=$rps-status --duration=$rps-duration | slurp))]
+}}
+set edit:after-command = [$@edit:after-command $rps-after-command~]
+set edit:before-readline = [$@edit:before-readline $rps-render~]
+set edit:prompt = {{ print $rps-prompts[0] }}
+set edit:rprompt = {{ if (> (count $rps-prompts) 1) {{ print $rps-prompts[1] }} }}
+"#,
+        elvish_quote(&exe_path)
+    )
+}
+
+/// A Python string literal for `text`, escaping everything outside printable
+/// ASCII so that the script doesn't depend on its encoding
+fn python_quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' | '"' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            ' '..='~' => quoted.push(c),
+            '\0'..='\u{ff}' => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            '\u{100}'..='\u{ffff}' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => quoted.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn init_script_xonsh(exe_path: String) -> String {
+    format!(
+        r#"import shutil as _rps_shutil
+import subprocess as _rps_subprocess
+
+_rps_state = {{"right": "", "history_len": 0}}
+
+
+def _rps_prompt():
+    status = 0
+    duration = 0
+    history = __xonsh__.history
+    if history is not None and len(history) > 0:
+        last = history[-1]
+        status = last.rtn
+        if len(history) != _rps_state["history_len"]:
+            _rps_state["history_len"] = len(history)
+            duration = int((last.ts[1] - last.ts[0]) * 1000)
+    jobs = list(getattr(__xonsh__, "all_jobs", {{}}).values())
+    stopped = sum(1 for job in jobs if job.get("status") in ("stopped", "suspended"))
+    output = _rps_subprocess.run(
+        [
+            {},
+            "--shell=xonsh",
+            "--columns={{}}".format(_rps_shutil.get_terminal_size().columns),
+            "--status={{}}".format(status),
+            "--running-jobs={{}}".format(len(jobs) - stopped),
+            "--stopped-jobs={{}}".format(stopped),
+            "--duration={{}}".format(duration),
+        ],
+        stdout=_rps_subprocess.PIPE,
+        text=True,
+    ).stdout
+    left, _, right = output.partition("\0")
+    _rps_state["right"] = right
+    return left
+
+
+$PROMPT = _rps_prompt
+$RIGHT_PROMPT = lambda: _rps_state["right"]
+"#,
+        python_quote(&exe_path)
+    )
+}
+
+pub fn echo_init_script(shell: Shell, transient: bool) {
     let path = get_exe_path();
     let string = match path {
         None => String::from("echo 'Error getting executable path for prompt'; (exit 1)"),
         Some(path) => match shell {
-            Shell::Zsh => init_script_zsh(path),
-            Shell::Fish => init_script_fish(path),
+            Shell::Zsh => init_script_zsh(path, transient),
+            Shell::Fish => init_script_fish(path, transient),
             Shell::Bash => init_script_bash(path),
+            Shell::Nu => init_script_nu(path),
+            Shell::Pwsh => init_script_pwsh(path),
+            Shell::Elvish => init_script_elvish(path),
+            Shell::Xonsh => init_script_xonsh(path),
         },
     };
     println!("{}", string);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{elvish_quote, fish_quote, nu_quote, posix_quote, pwsh_quote, python_quote};
+
+    #[test]
+    fn shell_words() {
+        let path = "/it's \\ \"$(x)\"/rps";
+        assert_eq!(posix_quote(path), "'/it'\\''s \\ \"$(x)\"/rps'");
+        assert_eq!(fish_quote(path), "'/it\\'s \\\\ \"$(x)\"/rps'");
+        assert_eq!(nu_quote(path), "\"/it's \\\\ \\\"$(x)\\\"/rps\"");
+        assert_eq!(pwsh_quote(path), "'/it''s \\ \"$(x)\"/rps'");
+        assert_eq!(pwsh_quote("/it\u{2019}s"), "'/it\u{2019}\u{2019}s'");
+        assert_eq!(elvish_quote(path), "'/it''s \\ \"$(x)\"/rps'");
+    }
+
+    #[test]
+    fn python_string_literals() {
+        assert_eq!(python_quote("/usr/bin/rps"), "\"/usr/bin/rps\"");
+        assert_eq!(
+            python_quote("/a \"b\" \\c/\u{e9}\n\u{3042}\u{1F980}"),
+            "\"/a \\\"b\\\" \\\\c/\\xe9\\x0a\\u3042\\U0001f980\""
+        );
+    }
+}
@@ -1,41 +1,93 @@
 use crate::{
     colors,
     segments::{Context, PromptSegment, RenderedSegment, ShrinkPriority},
+    width::width,
 };
 
+const RUNNING_SYMBOL: char = '\u{2699}';
+const STOPPED_SYMBOL: char = '\u{25A0}';
+
+/// Running jobs are shown in the segment's own color and stopped ones in red,
+/// since a forgotten stopped job is easier to lose work in
 pub struct JobsSegment {
-    jobs: usize,
+    running: usize,
+    stopped: usize,
+}
+
+/// The symbol, with the count in front of it unless there's only one job
+fn count_text(count: usize, symbol: char) -> String {
+    if count == 1 {
+        symbol.to_string()
+    } else {
+        format!("{} {}", count, symbol)
+    }
 }
 
 impl JobsSegment {
     pub fn new(context: &Context) -> Option<Self> {
-        if context.jobs == 0 {
+        if context.running_jobs == 0 && context.stopped_jobs == 0 {
             None
         } else {
-            Some(JobsSegment { jobs: context.jobs })
+            Some(JobsSegment {
+                running: context.running_jobs,
+                stopped: context.stopped_jobs,
+            })
         }
     }
 
-    fn get_unconstrained_size(&self) -> usize {
-        format!("{}", self.jobs).len() + 4
+    /// The running and stopped parts of the text, if there are any of each
+    fn parts(&self, with_counts: bool) -> (Option<String>, Option<String>) {
+        let part = |count: usize, symbol: char| match count {
+            0 => None,
+            _ if with_counts => Some(count_text(count, symbol)),
+            _ => Some(symbol.to_string()),
+        };
+        (
+            part(self.running, RUNNING_SYMBOL),
+            part(self.stopped, STOPPED_SYMBOL),
+        )
+    }
+
+    fn get_size(&self, with_counts: bool) -> usize {
+        let (running, stopped) = self.parts(with_counts);
+        [running, stopped]
+            .into_iter()
+            .flatten()
+            .map(|x| width(&x) + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    fn render_parts(&self, with_counts: bool) -> String {
+        let (running, stopped) = self.parts(with_counts);
+        let mut text = String::from(" ");
+        if let Some(running) = running {
+            text.push_str(&running);
+            text.push(' ');
+        }
+        if let Some(stopped) = stopped {
+            text.push_str(&colors::RED.fg_sequence());
+            text.push_str(&stopped);
+            text.push(' ');
+        }
+        text
     }
 }
 
 impl PromptSegment for JobsSegment {
     fn get_base_width(&self, shrink: crate::segments::ShrinkPriority) -> usize {
         match shrink {
-            ShrinkPriority::Unconstrained => match self.jobs {
-                1 => 3,
-                _ => self.get_unconstrained_size(),
-            },
+            ShrinkPriority::Unconstrained => self.get_size(true),
             ShrinkPriority::ShrinkComfortable => 3,
             ShrinkPriority::ShrinkBeyondMin => 0,
         }
     }
 
     fn get_actual_width_when_under(&self, max_size: usize) -> usize {
-        if max_size >= self.get_unconstrained_size() + 2 {
-            self.get_unconstrained_size() + 2
+        if max_size >= self.get_size(true) {
+            self.get_size(true)
+        } else if max_size >= self.get_size(false) {
+            self.get_size(false)
         } else if max_size >= 3 {
             3
         } else {
@@ -44,14 +96,17 @@ impl PromptSegment for JobsSegment {
     }
 
     fn render_at_size(&self, max_size: usize) -> RenderedSegment {
-        let text = if max_size >= self.get_unconstrained_size() {
-            if self.jobs == 1 {
-                String::from(" ⚙ ")
+        let text = if max_size >= self.get_size(true) {
+            self.render_parts(true)
+        } else if max_size >= self.get_size(false) {
+            self.render_parts(false)
+        } else if max_size >= 3 {
+            // Stopped jobs matter more, so they get the only symbol
+            if self.stopped != 0 {
+                format!(" {}{} ", colors::RED.fg_sequence(), STOPPED_SYMBOL)
             } else {
-                format!(" {} ⚙ ", self.jobs)
+                format!(" {} ", RUNNING_SYMBOL)
             }
-        } else if max_size >= 3 {
-            String::from(" ⚙ ")
         } else {
             String::new()
         };
@@ -66,16 +121,18 @@ impl PromptSegment for JobsSegment {
 
 #[cfg(test)]
 mod tests {
-    use crate::segments::{Context, PromptSegment, ShrinkPriority};
+    use crate::{
+        colors,
+        segments::{Context, PromptSegment, ShrinkPriority},
+    };
 
     use super::JobsSegment;
 
     #[test]
     fn render_single_job() {
         let context = Context {
-            path: None,
-            pipestatus: None,
-            jobs: 1,
+            running_jobs: 1,
+            ..Context::for_test()
         };
         let segment = JobsSegment::new(&context).unwrap();
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 3);
@@ -85,9 +142,8 @@ mod tests {
     #[test]
     fn render_multiple_jobs() {
         let context = Context {
-            path: None,
-            pipestatus: None,
-            jobs: 3,
+            running_jobs: 3,
+            ..Context::for_test()
         };
         let segment = JobsSegment::new(&context).unwrap();
         assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 5);
@@ -97,12 +153,48 @@ mod tests {
     #[test]
     fn render_constrained() {
         let context = Context {
-            path: None,
-            pipestatus: None,
-            jobs: 3,
+            running_jobs: 3,
+            ..Context::for_test()
         };
         let segment = JobsSegment::new(&context).unwrap();
         assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 3);
         assert_eq!(segment.render_at_size(3).text, " ⚙ ");
     }
+
+    #[test]
+    fn render_running_and_stopped() {
+        let context = Context {
+            running_jobs: 2,
+            stopped_jobs: 1,
+            ..Context::for_test()
+        };
+        let red = colors::RED.fg_sequence();
+        let segment = JobsSegment::new(&context).unwrap();
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 7);
+        assert_eq!(
+            segment.render_at_size(7).text,
+            format!(" 2 \u{2699} {}\u{25A0} ", red)
+        );
+        assert_eq!(segment.get_actual_width_when_under(6), 5);
+        assert_eq!(
+            segment.render_at_size(6).text,
+            format!(" \u{2699} {}\u{25A0} ", red)
+        );
+        assert_eq!(segment.render_at_size(4).text, format!(" {}\u{25A0} ", red));
+        assert_eq!(segment.render_at_size(2).text, "");
+    }
+
+    #[test]
+    fn render_stopped_only() {
+        let context = Context {
+            stopped_jobs: 2,
+            ..Context::for_test()
+        };
+        let segment = JobsSegment::new(&context).unwrap();
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 5);
+        assert_eq!(
+            segment.render_at_size(5).text,
+            format!(" {}2 \u{25A0} ", colors::RED.fg_sequence())
+        );
+    }
 }
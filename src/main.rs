@@ -1,29 +1,52 @@
 #![feature(iter_intersperse)]
 
 mod colors;
+mod config;
+mod daemon;
+mod duration;
+mod escape;
 mod git;
+mod host;
+mod hyperlink;
 mod init;
 mod jobs;
 mod path;
+mod python;
+mod rust;
 mod segments;
 mod status;
+mod theme;
+mod width;
 
 use std::{
     cmp::min,
     io::{self, Write},
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use colors::{get_color_depth, ColorScheme};
+use config::{load_config, Config, SegmentKind};
+use duration::CmdDurationSegment;
+use escape::{get_escape_backend, EscapeBackend, FishEscape};
 use git::GitSegment;
+use host::HostSegment;
 use init::echo_init_script;
 use jobs::JobsSegment;
 use path::PathSegment;
+use python::PythonEnvSegment;
+use rust::RustSegment;
 use segments::*;
-use status::StatusSegment;
+use status::{StatusSegment, FAILURE_SYMBOL};
 
 const SEGMENT_SEPARATOR: char = '\u{E0B0}';
-const MIN_WHITESPACE: usize = 40;
+const RIGHT_SEGMENT_SEPARATOR: char = '\u{E0B2}';
+const TRANSIENT_SYMBOL: char = '\u{276F}';
 
+#[allow(clippy::enum_variant_names)]
 #[derive(PartialEq, Eq, Debug)]
 enum Line {
     SingleLine,
@@ -32,7 +55,7 @@ enum Line {
 }
 
 struct SegmentLayout<'a> {
-    segment: &'a Box<dyn PromptSegment>,
+    segment: &'a dyn PromptSegment,
     current_size: usize,
 }
 
@@ -53,35 +76,35 @@ fn amount_can_shrink(segment_layout: &SegmentLayout, shrink_level: ShrinkPriorit
     segment_layout.current_size.saturating_sub(base_width)
 }
 
-fn layout_segments(
-    segments: &Vec<Box<dyn PromptSegment>>,
-    term_width: usize,
-    min_whitespace: usize,
-) -> (Line, Layout) {
-    let mut layout: Layout = segments
+fn initial_layout(segments: &[Box<dyn PromptSegment>]) -> Layout {
+    segments
         .iter()
         .map(|x| SegmentLayout {
-            segment: x,
+            segment: x.as_ref(),
             current_size: x.get_base_width(ShrinkPriority::Unconstrained),
         })
-        .collect();
-    let mut prompt_width = get_size(&layout);
+        .collect()
+}
 
-    if term_width.saturating_sub(prompt_width) > min_whitespace {
-        return (Line::SingleLine, layout);
+/// Shrinks segments until the layout fits in `max_width` or can't shrink any
+/// further, returning the resulting width
+fn shrink_layout(layout: &mut Layout, max_width: usize) -> usize {
+    let mut prompt_width = get_size(layout);
+    if layout.is_empty() {
+        return prompt_width;
     }
 
     for shrink_priority in [
         ShrinkPriority::ShrinkComfortable,
         ShrinkPriority::ShrinkBeyondMin,
     ] {
-        while prompt_width > term_width {
-            let amount_to_shrink = prompt_width - term_width;
+        while prompt_width > max_width {
+            let amount_to_shrink = prompt_width - max_width;
             let to_shrink = layout
                 .iter_mut()
-                .max_by_key(|segment| amount_can_shrink(&segment, shrink_priority))
+                .max_by_key(|segment| amount_can_shrink(segment, shrink_priority))
                 .unwrap();
-            let amount_can_shrink = amount_can_shrink(&to_shrink, shrink_priority);
+            let amount_can_shrink = amount_can_shrink(to_shrink, shrink_priority);
             if amount_can_shrink == 0 {
                 break;
             }
@@ -92,34 +115,199 @@ fn layout_segments(
                 .segment
                 .get_actual_width_when_under(new_requested_size);
             to_shrink.current_size = new_actual_size;
-            prompt_width = get_size(&layout);
+            prompt_width = get_size(layout);
         }
     }
 
-    if prompt_width > term_width {
-        return (Line::OverflowLine, layout);
+    prompt_width
+}
+
+/// Lays out the left and right prompts. The right prompt shrinks first, and
+/// is dropped entirely before the left prompt is split onto its own line.
+/// Segments shrunk to nothing are left out, so they don't leave a separator.
+fn layout_segments<'a>(
+    segments: &'a [Box<dyn PromptSegment>],
+    right_segments: &'a [Box<dyn PromptSegment>],
+    term_width: usize,
+    min_whitespace: usize,
+) -> (Line, Layout<'a>, Layout<'a>) {
+    let mut layout = initial_layout(segments);
+    let mut right_layout = initial_layout(right_segments);
+    let prompt_width = get_size(&layout);
+
+    if !right_layout.is_empty() {
+        let max_right_width = term_width.saturating_sub(prompt_width + min_whitespace + 1);
+        let right_width = shrink_layout(&mut right_layout, max_right_width);
+        if right_width <= max_right_width {
+            right_layout.retain(|x| x.current_size > 0);
+            return (Line::SingleLine, layout, right_layout);
+        }
+        right_layout.clear();
     }
 
-    (Line::SplitLine, layout)
+    if term_width.saturating_sub(prompt_width) > min_whitespace {
+        return (Line::SingleLine, layout, right_layout);
+    }
+
+    if shrink_layout(&mut layout, term_width) > term_width {
+        return (Line::OverflowLine, layout, right_layout);
+    }
+
+    layout.retain(|x| x.current_size > 0);
+    (Line::SplitLine, layout, right_layout)
 }
 
-fn set_stdout_color(fg: &colors::Color, bg: &colors::Color) {
-    // if *fg == colors::DEFAULT {
-    //     print!("%f");
-    // } else {
-    //     print!("%F{{{}}}", fg.name);
-    // }
-    // if *bg == colors::DEFAULT {
-    //     print!("%k");
-    // } else {
-    //     print!("%K{{{}}}", bg.name);
-    // }
-    print!("\x1b[{}m\x1b[{}m", fg.fg, bg.bg);
+fn color_sequence(
+    escape: &dyn EscapeBackend,
+    scheme: &ColorScheme,
+    fg: &colors::Color,
+    bg: &colors::Color,
+) -> String {
+    escape.non_printing(&scheme.sequence(fg, bg))
 }
 
-fn reset_stdout_color() {
-    // print!("%{{%f%k%}}");
-    print!("\x1b[0m");
+fn reset_sequence(escape: &dyn EscapeBackend) -> String {
+    escape.non_printing("\x1b[0m")
+}
+
+fn render_left_prompt(
+    rendered: &[RenderedSegment],
+    line_type: &Line,
+    escape: &dyn EscapeBackend,
+    scheme: &ColorScheme,
+) -> String {
+    let mut result = String::new();
+
+    if *line_type == Line::OverflowLine {
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &colors::DEFAULT,
+            &colors::BLUE,
+        ));
+        result.push(SEGMENT_SEPARATOR);
+        result.push_str(&reset_sequence(escape));
+        return result;
+    }
+
+    for (i, segment) in rendered.iter().enumerate() {
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &segment.fg_color,
+            &segment.bg_color,
+        ));
+        result.push_str(&escape.escape(&scheme.translate(&segment.text)));
+        let next_bg_color = rendered.get(i + 1).map_or(colors::DEFAULT, |x| x.bg_color);
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &segment.bg_color,
+            &next_bg_color,
+        ));
+        result.push(SEGMENT_SEPARATOR);
+    }
+
+    result.push_str(&reset_sequence(escape));
+    match line_type {
+        Line::SingleLine => {
+            result.push(' ');
+        }
+        _ => {
+            result.push('\n');
+            result.push_str(&color_sequence(
+                escape,
+                scheme,
+                &colors::BLACK,
+                &colors::BLUE,
+            ));
+            result.push_str(" ↳ ");
+            result.push_str(&color_sequence(
+                escape,
+                scheme,
+                &colors::BLUE,
+                &colors::DEFAULT,
+            ));
+            result.push(SEGMENT_SEPARATOR);
+            result.push_str(&reset_sequence(escape));
+            result.push(' ');
+        }
+    }
+    result
+}
+
+fn render_right_prompt(
+    rendered: &[RenderedSegment],
+    escape: &dyn EscapeBackend,
+    scheme: &ColorScheme,
+) -> String {
+    let mut result = String::new();
+    let mut previous_bg_color = colors::DEFAULT;
+    for segment in rendered {
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &segment.bg_color,
+            &previous_bg_color,
+        ));
+        result.push(RIGHT_SEGMENT_SEPARATOR);
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &segment.fg_color,
+            &segment.bg_color,
+        ));
+        result.push_str(&escape.escape(&scheme.translate(&segment.text)));
+        previous_bg_color = segment.bg_color;
+    }
+    result.push_str(&reset_sequence(escape));
+    result
+}
+
+/// The prompt left in scrollback after a command line is accepted: the
+/// failure symbol if the command before it failed, and an arrow
+fn render_transient_prompt(
+    pipestatus: Option<&str>,
+    escape: &dyn EscapeBackend,
+    scheme: &ColorScheme,
+) -> String {
+    let failed = pipestatus.map_or(false, |x| {
+        x.split_ascii_whitespace()
+            .filter_map(|x| x.parse::<i32>().ok())
+            .any(|x| x != 0)
+    });
+    let mut result = String::new();
+    if failed {
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &colors::RED,
+            &colors::DEFAULT,
+        ));
+        result.push(FAILURE_SYMBOL);
+        result.push(' ');
+    } else {
+        result.push_str(&color_sequence(
+            escape,
+            scheme,
+            &colors::GREEN,
+            &colors::DEFAULT,
+        ));
+    }
+    result.push(TRANSIENT_SYMBOL);
+    result.push_str(&reset_sequence(escape));
+    result.push(' ');
+    result
+}
+
+/// bash and pwsh have no right prompt, so it is drawn at the end of the line and the
+/// cursor is moved back before the left prompt is printed
+fn emulate_right_prompt(right_prompt: &str, right_width: usize, columns: usize) -> String {
+    format!(
+        "\x1b[s\x1b[{}G{}\x1b[u",
+        columns.saturating_sub(right_width) + 1,
+        right_prompt
+    )
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -127,16 +315,33 @@ pub enum Shell {
     Zsh,
     Bash,
     Fish,
+    Nu,
+    Pwsh,
+    Elvish,
+    Xonsh,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Keep repositories open and cache their state for later prompts
+    Daemon,
 }
 
 /// Terminal prompt in rust
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Echo init script for given shell
     #[arg(long, value_enum, value_name = "SHELL")]
     init: Option<Shell>,
 
+    /// The shell the prompt is rendered for, used to mark up escape sequences
+    #[arg(long, value_enum, value_name = "SHELL")]
+    shell: Option<Shell>,
+
     /// Status or pipestatus from the last run process
     #[arg(short, long, value_name = "PIPESTATUS")]
     status: Option<String>,
@@ -145,16 +350,117 @@ struct Args {
     #[arg(short, long, value_name = "COLS")]
     columns: Option<usize>,
 
-    /// The number of background jobs, from jobs -l | wc -l
+    /// The number of background jobs, for shells that can't tell running and
+    /// stopped jobs apart. Counted as running.
     #[arg(short, long, value_name = "JOBS")]
     jobs: Option<usize>,
+
+    /// The number of running background jobs, from jobs -r
+    #[arg(long, value_name = "JOBS")]
+    running_jobs: Option<usize>,
+
+    /// The number of stopped jobs, from jobs -s
+    #[arg(long, value_name = "JOBS")]
+    stopped_jobs: Option<usize>,
+
+    /// How long the last command took, in milliseconds
+    #[arg(short, long, value_name = "MS")]
+    duration: Option<u64>,
+
+    /// Render the short prompt that replaces the full one once a command line
+    /// is accepted
+    #[arg(long)]
+    transient: bool,
+
+    /// A directory the shell shows as ~NAME, like zsh's hash -d. Can be
+    /// given more than once.
+    #[arg(long, value_name = "NAME=PATH")]
+    named_dir: Vec<String>,
+
+    /// Config file to use instead of $XDG_CONFIG_HOME/rps/config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+fn build_segment(kind: SegmentKind, context: &Context) -> Option<Box<dyn PromptSegment>> {
+    match kind {
+        SegmentKind::Status => StatusSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Duration => CmdDurationSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Jobs => JobsSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Path => PathSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Git => GitSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Host => HostSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Python => PythonEnvSegment::new(context).map(|x| Box::new(x) as _),
+        SegmentKind::Rust => RustSegment::new(context).map(|x| Box::new(x) as _),
+    }
+}
+
+/// Builds every segment on its own thread, returning one entry for each of
+/// `kinds` in the same order. Segments that aren't built within `timeout` are
+/// replaced by a placeholder, or left out if `placeholder` is false, and their
+/// threads are left to finish on their own.
+fn build_segments<K: Copy + Send + 'static>(
+    kinds: &[K],
+    context: &Arc<Context>,
+    build: fn(K, &Context) -> Option<Box<dyn PromptSegment>>,
+    timeout: Option<Duration>,
+    placeholder: bool,
+) -> Vec<Option<Box<dyn PromptSegment>>> {
+    let (sender, receiver) = mpsc::channel();
+    for (i, kind) in kinds.iter().copied().enumerate() {
+        let sender = sender.clone();
+        let context = Arc::clone(context);
+        thread::spawn(move || {
+            let _ = sender.send((i, build(kind, &context)));
+        });
+    }
+    drop(sender);
+
+    let deadline = timeout.map(|x| Instant::now() + x);
+    let mut built: Vec<Option<Option<Box<dyn PromptSegment>>>> =
+        kinds.iter().map(|_| None).collect();
+    loop {
+        let received = match deadline {
+            Some(deadline) => receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .ok(),
+            None => receiver.recv().ok(),
+        };
+        match received {
+            Some((i, segment)) => built[i] = Some(segment),
+            None => break,
+        }
+    }
+
+    built
+        .into_iter()
+        .map(|x| match x {
+            Some(segment) => segment,
+            None if placeholder => Some(Box::new(TimedOutSegment) as _),
+            None => None,
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn run_daemon() {
+    if let Err(e) = daemon::run_daemon() {
+        eprintln!("rps: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_daemon() {
+    eprintln!("rps: the daemon is only supported on linux");
+    std::process::exit(1);
 }
 
 fn main() {
     let args = Args::parse();
 
-    if let Some(shell) = args.init {
-        echo_init_script(shell);
+    if let Some(Command::Daemon) = args.command {
+        run_daemon();
         return;
     }
 
@@ -165,63 +471,110 @@ fn main() {
     // println!("> ");
     // return;
 
-    let context = Context {
+    let config = load_config(args.config.as_deref()).unwrap_or_else(|e| {
+        eprintln!("rps: error in config file {}", e);
+        Config::default()
+    });
+
+    if let Some(shell) = args.init {
+        echo_init_script(shell, config.transient_prompt);
+        return;
+    }
+
+    if args.transient {
+        let escape = get_escape_backend(args.shell.as_ref());
+        let scheme = ColorScheme {
+            theme: theme::get_theme(&config.theme).unwrap_or(&theme::DEFAULT),
+            depth: get_color_depth(),
+        };
+        print!(
+            "{}",
+            render_transient_prompt(args.status.as_deref(), escape, &scheme)
+        );
+        let _ = io::stdout().flush();
+        return;
+    }
+
+    let context = Arc::new(Context {
         path: std::env::current_dir().ok(),
         pipestatus: args.status,
-        jobs: args.jobs.unwrap_or(0),
+        running_jobs: args.running_jobs.or(args.jobs).unwrap_or(0),
+        stopped_jobs: args.stopped_jobs.unwrap_or(0),
+        duration: args.duration.map(Duration::from_millis),
+        named_dirs: args
+            .named_dir
+            .iter()
+            .filter_map(|x| x.split_once('='))
+            .map(|(name, path)| (name.to_string(), PathBuf::from(path)))
+            .collect(),
+        config,
+    });
+
+    let escape = get_escape_backend(args.shell.as_ref());
+    let scheme = ColorScheme {
+        theme: theme::get_theme(&context.config.theme).unwrap_or(&theme::DEFAULT),
+        depth: get_color_depth(),
+    };
+
+    // Only shells that can show a right prompt get one
+    let has_right_prompt = args.shell.is_some() && args.columns.is_some();
+    let mut kinds = context.config.segments.clone();
+    if has_right_prompt {
+        kinds.extend(context.config.right_segments.iter().copied());
+    }
+    // Built together so the right prompt doesn't wait for the left one, and
+    // then split apart again
+    let timeout = match context.config.segment_timeout_ms {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
     };
+    let mut built = build_segments(
+        &kinds,
+        &context,
+        build_segment,
+        timeout,
+        context.config.show_timed_out_segments,
+    );
+    let right_built = built.split_off(context.config.segments.len());
+    let segments: Vec<Box<dyn PromptSegment>> = built.into_iter().flatten().collect();
+    let right_segments: Vec<Box<dyn PromptSegment>> = right_built.into_iter().flatten().collect();
 
-    let segments: Vec<Box<dyn PromptSegment>> = vec![
-        StatusSegment::new(&context).map(|x| Box::new(x) as Box<dyn PromptSegment>),
-        JobsSegment::new(&context).map(|x| Box::new(x) as Box<dyn PromptSegment>),
-        PathSegment::new(&context).map(|x| Box::new(x) as Box<dyn PromptSegment>),
-        GitSegment::new(&context).map(|x| Box::new(x) as Box<dyn PromptSegment>),
-    ]
-    .iter_mut()
-    .filter_map(|x| x.take())
-    .collect();
-
-    let (line_type, layout) = layout_segments(
+    let (line_type, layout, right_layout) = layout_segments(
         &segments,
+        &right_segments,
         args.columns.map(|x| x - 3).unwrap_or(usize::MAX),
-        MIN_WHITESPACE,
+        context.config.min_whitespace,
     );
 
-    if line_type == Line::OverflowLine {
-        set_stdout_color(&colors::DEFAULT, &colors::BLUE);
-        print!("{}", SEGMENT_SEPARATOR);
-        reset_stdout_color();
-        return;
-    }
-
     let rendered: Vec<RenderedSegment> = layout
         .iter()
         .map(|x| x.segment.render_at_size(x.current_size))
         .collect();
+    let left_prompt = render_left_prompt(&rendered, &line_type, escape, &scheme);
 
-    for (i, segment) in rendered.iter().enumerate() {
-        set_stdout_color(&segment.fg_color, &segment.bg_color);
-        print!("{}", segment.text);
-        let next_bg_color = rendered
-            .get(i + 1)
-            .map_or(colors::DEFAULT, |x| x.bg_color.clone());
-        set_stdout_color(&segment.bg_color, &next_bg_color);
-        print!("{}", SEGMENT_SEPARATOR);
-    }
+    let right_rendered: Vec<RenderedSegment> = right_layout
+        .iter()
+        .map(|x| x.segment.render_at_size(x.current_size))
+        .collect();
 
-    reset_stdout_color();
-    match line_type {
-        Line::SingleLine => {
-            print!(" ");
+    match args.shell {
+        _ if right_rendered.is_empty() => print!("{}", left_prompt),
+        Some(Shell::Bash) | Some(Shell::Pwsh) => {
+            let right_prompt = render_right_prompt(&right_rendered, &FishEscape, &scheme);
+            let right_width = get_size(&right_layout);
+            print!(
+                "{}{}",
+                escape.non_printing(&emulate_right_prompt(
+                    &right_prompt,
+                    right_width,
+                    args.columns.unwrap_or(0)
+                )),
+                left_prompt
+            );
         }
         _ => {
-            print!("\n");
-            set_stdout_color(&colors::BLACK, &colors::BLUE);
-            print!(" ↳ ");
-            set_stdout_color(&colors::BLUE, &colors::DEFAULT);
-            print!("{}", SEGMENT_SEPARATOR);
-            reset_stdout_color();
-            print!(" ");
+            let right_prompt = render_right_prompt(&right_rendered, escape, &scheme);
+            print!("{}\0{}", left_prompt, right_prompt);
         }
     }
 
@@ -230,10 +583,15 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, thread, time::Duration};
+
     use crate::{
-        layout_segments,
-        segments::{PromptSegment, ShrinkPriority},
-        Line,
+        build_segments,
+        colors::{ColorDepth, ColorScheme},
+        escape::FishEscape,
+        layout_segments, render_transient_prompt,
+        segments::{Context, PromptSegment, ShrinkPriority},
+        theme, Line,
     };
 
     const MIN_TEST_SEGMENT_SIZE: usize = 5;
@@ -266,7 +624,7 @@ mod tests {
     fn layout_segments_one_line() {
         let segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
 
-        let (line_type, layout) = layout_segments(&segments, 20, 5);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 20, 5);
         assert_eq!(line_type, Line::SingleLine);
         assert_eq!(layout[0].current_size, 10);
     }
@@ -275,7 +633,7 @@ mod tests {
     fn layout_segments_split_line() {
         let segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
 
-        let (line_type, layout) = layout_segments(&segments, 20, 10);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 20, 10);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, 10);
     }
@@ -284,7 +642,7 @@ mod tests {
     fn layout_segments_shrink_comfortable() {
         let segments = vec![Box::new(TestSegment { width: 25 }) as Box<dyn PromptSegment>];
 
-        let (line_type, layout) = layout_segments(&segments, 20, 10);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 20, 10);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, 18);
     }
@@ -293,7 +651,7 @@ mod tests {
     fn layout_segments_shrink_small() {
         let segments = vec![Box::new(TestSegment { width: 25 }) as Box<dyn PromptSegment>];
 
-        let (line_type, layout) = layout_segments(&segments, 6, 10);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 6, 10);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, 1);
     }
@@ -305,7 +663,7 @@ mod tests {
             Box::new(TestSegment { width: 30 }) as Box<dyn PromptSegment>,
         ];
 
-        let (line_type, layout) = layout_segments(&segments, 50, 40);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 50, 40);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, 25);
         assert_eq!(layout[1].current_size, 22);
@@ -318,7 +676,7 @@ mod tests {
             Box::new(TestSegment { width: 30 }) as Box<dyn PromptSegment>,
         ];
 
-        let (line_type, layout) = layout_segments(&segments, 25, 40);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 25, 40);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, 25 - MIN_TEST_SEGMENT_SIZE - 3);
         assert_eq!(layout[1].current_size, MIN_TEST_SEGMENT_SIZE);
@@ -331,7 +689,7 @@ mod tests {
             Box::new(TestSegment { width: 30 }) as Box<dyn PromptSegment>,
         ];
 
-        let (line_type, layout) = layout_segments(&segments, 10, 40);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 10, 40);
         assert_eq!(line_type, Line::SplitLine);
         assert_eq!(layout[0].current_size, MIN_TEST_SEGMENT_SIZE);
         assert_eq!(layout[1].current_size, 1);
@@ -344,9 +702,174 @@ mod tests {
             Box::new(TestSegment { width: 30 }) as Box<dyn PromptSegment>,
         ];
 
-        let (line_type, layout) = layout_segments(&segments, 3, 40);
+        let (line_type, layout, _) = layout_segments(&segments, &[], 3, 40);
         assert_eq!(line_type, Line::OverflowLine);
         assert_eq!(layout[0].current_size, 1);
         assert_eq!(layout[1].current_size, 1);
     }
+
+    #[test]
+    fn layout_right_prompt_fits() {
+        let segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+        let right_segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+
+        let (line_type, layout, right_layout) = layout_segments(&segments, &right_segments, 50, 20);
+        assert_eq!(line_type, Line::SingleLine);
+        assert_eq!(layout[0].current_size, 10);
+        assert_eq!(right_layout[0].current_size, 10);
+    }
+
+    #[test]
+    fn layout_right_prompt_shrinks_first() {
+        let segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+        let right_segments = vec![Box::new(TestSegment { width: 25 }) as Box<dyn PromptSegment>];
+
+        let (line_type, layout, right_layout) = layout_segments(&segments, &right_segments, 50, 20);
+        assert_eq!(line_type, Line::SingleLine);
+        assert_eq!(layout[0].current_size, 10);
+        assert_eq!(right_layout[0].current_size, 15);
+    }
+
+    #[test]
+    fn layout_right_prompt_dropped() {
+        let segments = vec![Box::new(TestSegment { width: 25 }) as Box<dyn PromptSegment>];
+        let right_segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+
+        let (line_type, layout, right_layout) = layout_segments(&segments, &right_segments, 50, 20);
+        assert_eq!(line_type, Line::SingleLine);
+        assert_eq!(layout[0].current_size, 25);
+        assert!(right_layout.is_empty());
+    }
+
+    #[test]
+    fn layout_right_prompt_dropped_before_split() {
+        let segments = vec![Box::new(TestSegment { width: 35 }) as Box<dyn PromptSegment>];
+        let right_segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+
+        let (line_type, layout, right_layout) = layout_segments(&segments, &right_segments, 50, 20);
+        assert_eq!(line_type, Line::SplitLine);
+        assert_eq!(layout[0].current_size, 35);
+        assert!(right_layout.is_empty());
+    }
+
+    /// As wide as a [`TestSegment`] of width 10 until it has to shrink past
+    /// that, and then gone
+    struct VanishingSegment;
+    impl PromptSegment for VanishingSegment {
+        fn get_base_width(&self, shrink: crate::segments::ShrinkPriority) -> usize {
+            match shrink {
+                ShrinkPriority::ShrinkBeyondMin => 0,
+                _ => 10,
+            }
+        }
+
+        fn get_actual_width_when_under(&self, max_size: usize) -> usize {
+            if max_size >= 10 {
+                10
+            } else {
+                0
+            }
+        }
+
+        fn render_at_size(&self, _max_size: usize) -> crate::segments::RenderedSegment {
+            todo!()
+        }
+    }
+
+    #[test]
+    fn layout_right_prompt_drops_empty_segments() {
+        let segments = vec![Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>];
+        let right_segments = vec![
+            Box::new(VanishingSegment) as Box<dyn PromptSegment>,
+            Box::new(TestSegment { width: 10 }) as Box<dyn PromptSegment>,
+        ];
+
+        let (line_type, _, right_layout) = layout_segments(&segments, &right_segments, 30, 5);
+        assert_eq!(line_type, Line::SingleLine);
+        assert_eq!(right_layout.len(), 1);
+        assert_eq!(right_layout[0].current_size, MIN_TEST_SEGMENT_SIZE);
+    }
+
+    /// Takes `width` milliseconds to build, and isn't shown if `width` is 0
+    fn build_slow_segment(width: usize, _context: &Context) -> Option<Box<dyn PromptSegment>> {
+        thread::sleep(Duration::from_millis(width as u64));
+        if width == 0 {
+            None
+        } else {
+            Some(Box::new(TestSegment { width }))
+        }
+    }
+
+    fn test_context() -> Arc<Context> {
+        Arc::new(Context::for_test())
+    }
+
+    #[test]
+    fn build_segments_keeps_order() {
+        let segments = build_segments(
+            &[30, 0, 10, 20],
+            &test_context(),
+            build_slow_segment,
+            None,
+            true,
+        );
+        let widths: Vec<Option<usize>> = segments
+            .iter()
+            .map(|x| {
+                x.as_ref()
+                    .map(|x| x.get_base_width(ShrinkPriority::Unconstrained))
+            })
+            .collect();
+        assert_eq!(widths, vec![Some(30), None, Some(10), Some(20)]);
+    }
+
+    #[test]
+    fn build_segments_replaces_timed_out() {
+        let segments = build_segments(
+            &[5, 2000, 10],
+            &test_context(),
+            build_slow_segment,
+            Some(Duration::from_millis(500)),
+            true,
+        );
+        let widths: Vec<Option<usize>> = segments
+            .iter()
+            .map(|x| {
+                x.as_ref()
+                    .map(|x| x.get_base_width(ShrinkPriority::Unconstrained))
+            })
+            .collect();
+        // The placeholder is 3 wide
+        assert_eq!(widths, vec![Some(5), Some(3), Some(10)]);
+
+        let segments = build_segments(
+            &[5, 2000],
+            &test_context(),
+            build_slow_segment,
+            Some(Duration::from_millis(500)),
+            false,
+        );
+        assert!(segments[0].is_some());
+        assert!(segments[1].is_none());
+    }
+
+    #[test]
+    fn transient_prompt_shows_failure() {
+        let scheme = ColorScheme {
+            theme: &theme::DEFAULT,
+            depth: ColorDepth::TrueColor,
+        };
+        assert_eq!(
+            render_transient_prompt(Some("0"), &FishEscape, &scheme),
+            "\x1b[32m\x1b[49m\u{276F}\x1b[0m "
+        );
+        assert_eq!(
+            render_transient_prompt(None, &FishEscape, &scheme),
+            "\x1b[32m\x1b[49m\u{276F}\x1b[0m "
+        );
+        assert_eq!(
+            render_transient_prompt(Some("0 130"), &FishEscape, &scheme),
+            "\x1b[31m\x1b[49m\u{2718} \u{276F}\x1b[0m "
+        );
+    }
 }
@@ -1,18 +1,38 @@
-use std::{borrow::Cow, path::PathBuf};
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     colors,
+    config::{PathConfig, PathStrategy},
+    git::open_repository,
+    host::hostname,
+    hyperlink::{file_url, hyperlink},
     segments::{Context, PromptSegment, RenderedSegment, ShrinkPriority},
+    width::{take_width_from_end, width},
 };
 
 const PATH_SEPARATOR: char = '\u{E0B1}';
-const MIN_PATH_SIZE: usize = 6;
+const READ_ONLY_SYMBOL: char = '\u{E0A2}';
+/// Makes the repository name bold, and then turns bold off again
+const REPO_NAME_START: &str = "\x1b[1m";
+const REPO_NAME_END: &str = "\x1b[22m";
 
 #[derive(Debug, PartialEq)]
 enum PathType {
     RelativeToHome,
     RelativeToRoot,
+    /// Relative to the root of the working directory of a git repository
+    RelativeToRepo(PathBuf),
+    /// Relative to a directory that is shown as `alias`
+    Aliased {
+        alias: String,
+        target: PathBuf,
+    },
     Nonexistent,
 }
 
@@ -33,51 +53,339 @@ fn get_path_relative_to_home(cwd: &PathBuf) -> (PathType, Cow<PathBuf>) {
     #[allow(deprecated)]
     match std::env::home_dir() {
         Some(home) => {
-            let relative_path = get_relative_path(cwd, &home);
+            let relative_path = get_relative_path(cwd, home);
             (relative_path.0, Cow::Owned(relative_path.1))
         }
         None => (PathType::RelativeToRoot, Cow::Borrowed(cwd)),
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DirState {
+    Writable,
+    /// The user can't create files in the directory
+    ReadOnly,
+    /// The directory was deleted after the shell entered it
+    Deleted,
+}
+
+/// The path to show, and whether it was deleted. When the working directory
+/// can't be found, the shell's `$PWD` is the last place it was known to be.
+/// It is deleted even if `$PWD` exists, since that is a new directory made
+/// in its place, like after `rm -rf build && mkdir build`.
+fn get_last_known_path(current: Option<PathBuf>, pwd: Option<PathBuf>) -> Option<(PathBuf, bool)> {
+    match (current, pwd) {
+        (Some(current), _) if current.exists() => Some((current, false)),
+        (_, Some(pwd)) if pwd.is_absolute() => Some((pwd, true)),
+        (Some(current), _) => Some((current, true)),
+        (None, _) => None,
+    }
+}
+
+fn is_writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Finds the directory in `candidates` that is the longest prefix of `cwd`,
+/// returning its type and the rest of the path. Later candidates win ties.
+fn get_aliased_path(
+    cwd: &Path,
+    candidates: Vec<(PathType, PathBuf)>,
+) -> Option<(PathType, PathBuf)> {
+    candidates
+        .into_iter()
+        .filter_map(|(path_type, target)| {
+            let rest = cwd.strip_prefix(&target).ok()?.to_path_buf();
+            Some((path_type, target.components().count(), rest))
+        })
+        .max_by_key(|(_, length, _)| *length)
+        .map(|(path_type, _, rest)| (path_type, rest))
+}
+
+/// Every directory that is shown by another name: the home directory, the
+/// shell's named directories and the configured aliases, in that order
+fn alias_candidates(context: &Context) -> Vec<(PathType, PathBuf)> {
+    #[allow(deprecated)]
+    let home = std::env::home_dir();
+    let mut candidates: Vec<(PathType, PathBuf)> = Vec::new();
+    if let Some(home) = home.as_ref() {
+        candidates.push((PathType::RelativeToHome, home.clone()));
+    }
+    for (name, target) in &context.named_dirs {
+        candidates.push((
+            PathType::Aliased {
+                alias: format!("~{}", name),
+                target: target.clone(),
+            },
+            target.clone(),
+        ));
+    }
+    for (target, alias) in &context.config.path.aliases {
+        let target = match (target.strip_prefix('~'), home.as_ref()) {
+            (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+            _ => PathBuf::from(target),
+        };
+        candidates.push((
+            PathType::Aliased {
+                alias: alias.clone(),
+                target: target.clone(),
+            },
+            target,
+        ));
+    }
+    candidates
+}
+
+/// The path inside the repository `cwd` is in, if it is in one with a
+/// working directory
+fn get_path_relative_to_repo(cwd: &Path) -> Option<(PathType, PathBuf)> {
+    let repo = open_repository(cwd)?;
+    let root = repo.workdir()?;
+    let relative = cwd.strip_prefix(root).ok()?.to_path_buf();
+    Some((PathType::RelativeToRepo(root.to_path_buf()), relative))
+}
+
+/// What the path starts with, before the first separator
+fn prefix_text(path_type: &PathType) -> Cow<str> {
+    match path_type {
+        PathType::RelativeToHome => Cow::Borrowed("~"),
+        PathType::RelativeToRoot => Cow::Borrowed("/"),
+        PathType::RelativeToRepo(root) => match root.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => Cow::Borrowed("/"),
+        },
+        PathType::Aliased { alias, .. } => Cow::Borrowed(alias),
+        PathType::Nonexistent => Cow::Borrowed(""),
+    }
+}
+
+/// The directory a path of the given type is relative to
+fn base_dir(path_type: &PathType) -> Option<PathBuf> {
+    match path_type {
+        #[allow(deprecated)]
+        PathType::RelativeToHome => std::env::home_dir(),
+        PathType::RelativeToRoot => Some(PathBuf::from("/")),
+        PathType::RelativeToRepo(root) => Some(root.clone()),
+        PathType::Aliased { target, .. } => Some(target.clone()),
+        PathType::Nonexistent => None,
+    }
+}
+
+/// The first `length` letters of `name`, plus a leading `.` if it has one
+fn abbreviate(name: &str, length: usize) -> String {
+    let length = if name.starts_with('.') {
+        length + 1
+    } else {
+        length
+    };
+    name.graphemes(true).take(length.max(1)).collect()
+}
+
+/// The shortest prefix of `name` that none of `siblings` start with, or all
+/// of `name` if every prefix is shared
+fn unique_prefix<'a>(name: &str, siblings: impl Iterator<Item = &'a str> + Clone) -> String {
+    let mut prefix = String::new();
+    for grapheme in name.graphemes(true) {
+        prefix.push_str(grapheme);
+        if prefix == "." {
+            continue;
+        }
+        if !siblings
+            .clone()
+            .any(|x| x != name && x.starts_with(prefix.as_str()))
+        {
+            break;
+        }
+    }
+    prefix
+}
+
+/// Shortens each directory the path goes through, leaving the last
+/// component whole. Components that can't be listed are left whole with the
+/// `unique` strategy.
+fn shorten_components(
+    components: &[String],
+    base: Option<&Path>,
+    options: &PathConfig,
+) -> Vec<String> {
+    let Some((last, parents)) = components.split_last() else {
+        return Vec::new();
+    };
+    let mut directory = base.map(Path::to_path_buf);
+    let mut shortened: Vec<String> = parents
+        .iter()
+        .map(|name| {
+            let short = match options.strategy {
+                PathStrategy::Truncate => name.clone(),
+                PathStrategy::Abbreviate => abbreviate(name, options.abbreviated_length),
+                PathStrategy::Unique => {
+                    let siblings: Option<Vec<String>> = directory
+                        .as_ref()
+                        .and_then(|x| std::fs::read_dir(x).ok())
+                        .map(|entries| {
+                            entries
+                                .filter_map(|x| x.ok())
+                                .map(|x| x.file_name().to_string_lossy().into_owned())
+                                .collect()
+                        });
+                    match siblings {
+                        Some(siblings) => unique_prefix(name, siblings.iter().map(String::as_str)),
+                        None => name.clone(),
+                    }
+                }
+            };
+            directory = directory.take().map(|x| x.join(name));
+            short
+        })
+        .collect();
+    shortened.push(last.clone());
+    shortened
+}
+
 pub struct PathSegment {
     path_segments: Vec<String>,
+    /// `path_segments` with every parent directory shortened
+    short_segments: Vec<String>,
     path_type: PathType,
     preferred_width: usize,
+    /// The width with the first `i` components shortened, for each `i`
+    shortened_widths: Vec<usize>,
+    state: DirState,
+    /// Links for the prefix and then each component, or nothing if they
+    /// aren't shown
+    urls: Vec<String>,
+    options: PathConfig,
 }
 
-fn calculate_preferred_size(components: &Vec<String>) -> usize {
-    components
-        .iter()
-        .map(|x| x.graphemes(true).count() + 3)
-        .sum::<usize>()
-        + 3
+/// `file://` URLs for `base` and every directory below it in `components`
+fn directory_urls(host: &str, base: &Path, components: &[String]) -> Vec<String> {
+    let mut directory = base.to_path_buf();
+    let mut urls = vec![file_url(host, &directory)];
+    for component in components {
+        directory.push(component);
+        urls.push(file_url(host, &directory));
+    }
+    urls
+}
+
+fn calculate_preferred_size(prefix: &str, components: &[String]) -> usize {
+    components.iter().map(|x| width(x) + 3).sum::<usize>() + width(prefix) + 2
+}
+
+/// Cuts the start off the path until it fits in `max_size` cells, padding
+/// after the `...` if a wide character had to be cut off whole
+fn truncate_path(components: &[String], separator: &str, max_size: usize) -> String {
+    let full_text = format!("{}{}", separator, components.join(separator));
+    let available = max_size.saturating_sub(5);
+    let kept = take_width_from_end(&full_text, available);
+    let padding = available - width(kept);
+    format!(" ...{}{} ", " ".repeat(padding), kept)
 }
 
 impl PathSegment {
     pub fn new(context: &Context) -> Option<Self> {
-        let (path_type, path_buf) = match context.path.as_ref() {
-            Some(path) => get_path_relative_to_home(path),
-            None => (PathType::Nonexistent, Cow::Owned(PathBuf::new())),
+        let last_known = get_last_known_path(
+            context.path.clone(),
+            std::env::var_os("PWD").map(PathBuf::from),
+        );
+        let path = last_known.as_ref().map(|(path, _)| path);
+        let state = match last_known.as_ref() {
+            Some((_, true)) => DirState::Deleted,
+            Some((path, false)) if !is_writable(path) => DirState::ReadOnly,
+            _ => DirState::Writable,
+        };
+
+        let repo_path = path
+            .filter(|_| context.config.path.relative_to_repo && state != DirState::Deleted)
+            .and_then(|x| get_path_relative_to_repo(x))
+            .or_else(|| get_aliased_path(path?, alias_candidates(context)));
+        let (path_type, path_buf) = match (repo_path, path) {
+            (Some((path_type, path)), _) => (path_type, Cow::Owned(path)),
+            (None, Some(path)) => get_path_relative_to_home(path),
+            (None, None) => (PathType::Nonexistent, Cow::Owned(PathBuf::new())),
         };
 
-        Some(Self::new_from_path(path_type, path_buf))
+        let mut segment = Self::new_from_path(path_type, path_buf, context.config.path.clone());
+        segment.state = state;
+        // Deleted directories have nothing to open
+        if context.config.hyperlinks && state != DirState::Deleted {
+            if let Some(base) = base_dir(&segment.path_type).filter(|x| x.is_absolute()) {
+                let host = hostname().unwrap_or_default();
+                segment.urls = directory_urls(&host, &base, &segment.path_segments);
+            }
+        }
+        Some(segment)
     }
 
-    fn new_from_path(path_type: PathType, path_buf: Cow<PathBuf>) -> Self {
+    fn new_from_path(path_type: PathType, path_buf: Cow<PathBuf>, options: PathConfig) -> Self {
         let components: Vec<String> = path_buf
             .iter()
             .map(|x| x.to_string_lossy().into_owned())
             .collect();
 
-        let preferred_width = calculate_preferred_size(&components);
+        let preferred_width = calculate_preferred_size(&prefix_text(&path_type), &components);
+        let short_segments =
+            shorten_components(&components, base_dir(&path_type).as_deref(), &options);
+
+        let mut shortened_widths = vec![preferred_width];
+        for (full, short) in components.iter().zip(&short_segments) {
+            let saved = width(full) - width(short);
+            shortened_widths.push(shortened_widths.last().unwrap() - saved);
+        }
 
         PathSegment {
             path_segments: components,
+            short_segments,
             path_type,
             preferred_width,
+            shortened_widths,
+            state: DirState::Writable,
+            urls: Vec::new(),
+            options,
+        }
+    }
+
+    /// Width of the start of the path that truncating it has to keep, since
+    /// aliases and the repository name are never cut off
+    fn kept_prefix_width(&self) -> usize {
+        match &self.path_type {
+            PathType::Aliased { .. } | PathType::RelativeToRepo(_) => {
+                width(&prefix_text(&self.path_type)) + 3
+            }
+            _ => 0,
+        }
+    }
+
+    /// Width of the lock shown after the path of a read-only directory
+    fn suffix_width(&self) -> usize {
+        match self.state {
+            DirState::ReadOnly => 2,
+            _ => 0,
+        }
+    }
+
+    /// Deleted directories are shown in red
+    fn bg_color(&self) -> colors::Color {
+        match self.state {
+            DirState::Deleted => colors::RED,
+            _ => colors::BLUE,
+        }
+    }
+
+    /// Links `text` to the `i`th of `urls`, if there is one
+    fn link(&self, i: usize, text: &str) -> String {
+        match self.urls.get(i) {
+            Some(url) => hyperlink(url, text),
+            None => text.to_string(),
         }
     }
+
+    fn min_truncated_width(&self) -> usize {
+        self.options.min_path_size + self.kept_prefix_width() + self.suffix_width()
+    }
 }
 
 impl PromptSegment for PathSegment {
@@ -87,8 +395,8 @@ impl PromptSegment for PathSegment {
         }
 
         match shrink {
-            ShrinkPriority::Unconstrained => self.preferred_width,
-            ShrinkPriority::ShrinkComfortable => MIN_PATH_SIZE,
+            ShrinkPriority::Unconstrained => self.preferred_width + self.suffix_width(),
+            ShrinkPriority::ShrinkComfortable => self.min_truncated_width(),
             ShrinkPriority::ShrinkBeyondMin => 1,
         }
     }
@@ -96,9 +404,14 @@ impl PromptSegment for PathSegment {
     fn get_actual_width_when_under(&self, max_size: usize) -> usize {
         if self.path_type == PathType::Nonexistent {
             3
-        } else if max_size >= self.preferred_width {
-            self.preferred_width
-        } else if max_size >= MIN_PATH_SIZE {
+        } else if let Some(width) = self
+            .shortened_widths
+            .iter()
+            .map(|x| x + self.suffix_width())
+            .find(|x| *x <= max_size)
+        {
+            width
+        } else if max_size >= self.min_truncated_width() {
             max_size
         } else {
             1
@@ -116,47 +429,67 @@ impl PromptSegment for PathSegment {
 
         let separator = format!(" {} ", PATH_SEPARATOR);
 
-        let prefix_char = match self.path_type {
-            PathType::RelativeToHome => '~',
-            PathType::RelativeToRoot => '/',
-            PathType::Nonexistent => unreachable!(),
+        let prefix = match &self.path_type {
+            PathType::RelativeToRepo(_) => format!(
+                "{}{}{}",
+                REPO_NAME_START,
+                prefix_text(&self.path_type),
+                REPO_NAME_END
+            ),
+            path_type => prefix_text(path_type).into_owned(),
         };
+        let prefix = self.link(0, &prefix);
+        let last = self.path_segments.len();
 
-        let text = if max_size >= self.preferred_width {
+        let shortened_count = self
+            .shortened_widths
+            .iter()
+            .position(|x| x + self.suffix_width() <= max_size);
+        let mut text = if let Some(count) = shortened_count {
             if self.path_segments.is_empty() {
-                format!(" {} ", prefix_char)
+                format!(" {} ", prefix)
             } else {
-                let full_text = self.path_segments.join(separator.as_str());
-                format!(" {}{}{} ", prefix_char, separator, full_text)
+                let components: Vec<String> = self.short_segments[..count]
+                    .iter()
+                    .chain(&self.path_segments[count..])
+                    .enumerate()
+                    .map(|(i, x)| self.link(i + 1, x))
+                    .collect();
+                let full_text = components.join(separator.as_str());
+                format!(" {}{}{} ", prefix, separator, full_text)
             }
-        } else if max_size >= MIN_PATH_SIZE {
-            let mut string_builder: Vec<&str> = vec![" "];
-            let mut current_size = 1;
-            'outer: for segment in self.path_segments.iter().rev() {
-                for c in segment
-                    .graphemes(true)
-                    .rev()
-                    .chain(separator.graphemes(true).rev())
-                {
-                    string_builder.push(c);
-                    current_size += 1;
-                    if current_size + 4 >= max_size {
-                        break 'outer;
-                    }
+        } else if max_size >= self.min_truncated_width() {
+            match &self.path_type {
+                PathType::Aliased { .. } | PathType::RelativeToRepo(_) => {
+                    let kept = format!(" {} {}", prefix, PATH_SEPARATOR);
+                    let rest = max_size - self.kept_prefix_width() - self.suffix_width();
+                    let truncated = truncate_path(&self.short_segments, &separator, rest);
+                    kept + &self.link(last, &truncated)
                 }
+                _ => self.link(
+                    last,
+                    &truncate_path(
+                        &self.short_segments,
+                        &separator,
+                        max_size - self.suffix_width(),
+                    ),
+                ),
             }
-            string_builder.push(" ...");
-            string_builder.into_iter().rev().collect()
         } else {
-            " ".to_string()
+            return RenderedSegment {
+                text: " ".to_string(),
+                bg_color: self.bg_color(),
+                fg_color: colors::BLACK,
+            };
         };
-        debug_assert_eq!(
-            text.graphemes(true).count(),
-            self.get_actual_width_when_under(max_size)
-        );
+        if self.state == DirState::ReadOnly {
+            text.push(READ_ONLY_SYMBOL);
+            text.push(' ');
+        }
+        debug_assert_eq!(width(&text), self.get_actual_width_when_under(max_size));
         RenderedSegment {
             text,
-            bg_color: colors::BLUE,
+            bg_color: self.bg_color(),
             fg_color: colors::BLACK,
         }
     }
@@ -164,14 +497,25 @@ impl PromptSegment for PathSegment {
 
 #[cfg(test)]
 mod tests {
-    use std::{borrow::Cow, path::PathBuf};
+    use std::{
+        borrow::Cow,
+        path::{Path, PathBuf},
+    };
 
     use crate::{
-        path::{get_relative_path, PathType, MIN_PATH_SIZE, PATH_SEPARATOR},
-        segments::PromptSegment,
+        colors,
+        config::{PathConfig, PathStrategy},
+        path::{get_relative_path, PathType, PATH_SEPARATOR},
+        segments::{PromptSegment, ShrinkPriority},
+        width::width,
     };
+    use proptest::prelude::*;
 
-    use super::PathSegment;
+    use super::{
+        abbreviate, directory_urls, get_aliased_path, get_last_known_path,
+        get_path_relative_to_repo, is_writable, shorten_components, unique_prefix, DirState,
+        PathSegment, READ_ONLY_SYMBOL, REPO_NAME_END, REPO_NAME_START,
+    };
 
     #[test]
     fn format_relative_to_home() {
@@ -214,7 +558,8 @@ mod tests {
         let home = PathBuf::from("/home/me");
         let cwd = PathBuf::from("/home/me/abc/de");
         let (path_type, path_buf) = get_relative_path(cwd, home);
-        let segment = PathSegment::new_from_path(path_type, Cow::Owned(path_buf));
+        let segment =
+            PathSegment::new_from_path(path_type, Cow::Owned(path_buf), PathConfig::default());
         assert_eq!(segment.preferred_width, " ~ > abc > de ".len());
     }
 
@@ -223,14 +568,18 @@ mod tests {
         let home = PathBuf::from("/home/me");
         let cwd = PathBuf::from("/home/me/");
         let (path_type, path_buf) = get_relative_path(cwd, home);
-        let segment = PathSegment::new_from_path(path_type, Cow::Owned(path_buf));
+        let segment =
+            PathSegment::new_from_path(path_type, Cow::Owned(path_buf), PathConfig::default());
         assert_eq!(segment.preferred_width, " ~ ".len());
     }
 
     #[test]
     fn render_home() {
-        let segment =
-            PathSegment::new_from_path(PathType::RelativeToHome, Cow::Owned(PathBuf::new()));
+        let segment = PathSegment::new_from_path(
+            PathType::RelativeToHome,
+            Cow::Owned(PathBuf::new()),
+            PathConfig::default(),
+        );
         let rendered = segment.render_at_size(segment.preferred_width);
         assert_eq!(rendered.text, " ~ ");
     }
@@ -240,6 +589,7 @@ mod tests {
         let segment = PathSegment::new_from_path(
             PathType::RelativeToHome,
             Cow::Owned(PathBuf::from("1234567890")),
+            PathConfig::default(),
         );
         let full_size = segment.render_at_size(segment.preferred_width);
         assert_eq!(full_size.text, format!(" ~ {} 1234567890 ", PATH_SEPARATOR));
@@ -252,6 +602,7 @@ mod tests {
         let segment = PathSegment::new_from_path(
             PathType::RelativeToHome,
             Cow::Owned(PathBuf::from("1234567890/1234")),
+            PathConfig::default(),
         );
         let full_size = segment.render_at_size(segment.preferred_width);
         assert_eq!(
@@ -270,6 +621,7 @@ mod tests {
         let segment = PathSegment::new_from_path(
             PathType::RelativeToRoot,
             Cow::Owned(PathBuf::from("1234567890/1234")),
+            PathConfig::default(),
         );
         let full_size = segment.render_at_size(segment.preferred_width);
         assert_eq!(
@@ -283,17 +635,347 @@ mod tests {
         let segment = PathSegment::new_from_path(
             PathType::RelativeToRoot,
             Cow::Owned(PathBuf::from("1234567890/1234")),
+            PathConfig::default(),
         );
-        let allowed = segment.render_at_size(MIN_PATH_SIZE);
+        let min_path_size = PathConfig::default().min_path_size;
+        let allowed = segment.render_at_size(min_path_size);
         assert_eq!(allowed.text, " ...4 ");
-        let smallest = segment.render_at_size(MIN_PATH_SIZE - 1);
+        let smallest = segment.render_at_size(min_path_size - 1);
         assert_eq!(smallest.text, " ");
     }
 
     #[test]
     fn missing_path() {
-        let segment = PathSegment::new_from_path(PathType::Nonexistent, Cow::Owned(PathBuf::new()));
+        let segment = PathSegment::new_from_path(
+            PathType::Nonexistent,
+            Cow::Owned(PathBuf::new()),
+            PathConfig::default(),
+        );
         let rendered = segment.render_at_size(segment.preferred_width);
         assert_eq!(rendered.text, " ✘ ");
     }
+
+    #[test]
+    fn abbreviate_components() {
+        assert_eq!(abbreviate("projects", 1), "p");
+        assert_eq!(abbreviate("projects", 3), "pro");
+        assert_eq!(abbreviate(".config", 1), ".c");
+        assert_eq!(abbreviate("ab", 5), "ab");
+    }
+
+    #[test]
+    fn unique_prefixes() {
+        let siblings = ["projects", "pictures", "proj", "rps", ".cargo", ".config"];
+        let prefix = |name| unique_prefix(name, siblings.iter().copied());
+        assert_eq!(prefix("pictures"), "pi");
+        assert_eq!(prefix("projects"), "proje");
+        assert_eq!(prefix("proj"), "proj");
+        assert_eq!(prefix("rps"), "r");
+        assert_eq!(prefix(".config"), ".co");
+    }
+
+    #[test]
+    fn shorten_unique_on_disk() {
+        let base = std::env::temp_dir().join(format!("rps-path-{}", std::process::id()));
+        for dir in ["projects/rps/src", "pictures", "rust"] {
+            std::fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        let components: Vec<String> = ["projects", "rps", "src"].map(String::from).into();
+        let options = PathConfig {
+            strategy: PathStrategy::Unique,
+            ..PathConfig::default()
+        };
+        let shortened = shorten_components(&components, Some(&base), &options);
+        std::fs::remove_dir_all(&base).unwrap();
+        assert_eq!(shortened, vec!["pr", "r", "src"]);
+    }
+
+    #[test]
+    fn render_abbreviated() {
+        let segment = PathSegment::new_from_path(
+            PathType::RelativeToHome,
+            Cow::Owned(PathBuf::from("projects/rps/src")),
+            PathConfig {
+                strategy: PathStrategy::Abbreviate,
+                ..PathConfig::default()
+            },
+        );
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 26);
+        assert_eq!(
+            segment.render_at_size(26).text,
+            format!(" ~ {0} projects {0} rps {0} src ", PATH_SEPARATOR)
+        );
+
+        // Parent directories are shortened one at a time, from the top
+        assert_eq!(segment.get_actual_width_when_under(25), 19);
+        assert_eq!(
+            segment.render_at_size(25).text,
+            format!(" ~ {0} p {0} rps {0} src ", PATH_SEPARATOR)
+        );
+        assert_eq!(segment.get_actual_width_when_under(18), 17);
+        assert_eq!(
+            segment.render_at_size(18).text,
+            format!(" ~ {0} p {0} r {0} src ", PATH_SEPARATOR)
+        );
+
+        // Then the shortened path is truncated
+        assert_eq!(segment.get_actual_width_when_under(12), 12);
+        assert_eq!(
+            segment.render_at_size(12).text,
+            format!(" ...r {} src ", PATH_SEPARATOR)
+        );
+    }
+
+    #[test]
+    fn relative_to_repo() {
+        let base = std::env::temp_dir().join(format!("rps-path-repo-{}", std::process::id()));
+        let root = base.join("project");
+        std::fs::create_dir_all(root.join("src/bin")).unwrap();
+        git2::Repository::init(&root).unwrap();
+        let in_repo = get_path_relative_to_repo(&root.join("src/bin"));
+        let outside = get_path_relative_to_repo(&base);
+        std::fs::remove_dir_all(&base).unwrap();
+
+        let (path_type, path) = in_repo.unwrap();
+        assert_eq!(path_type, PathType::RelativeToRepo(root.join("")));
+        assert_eq!(path, PathBuf::from("src/bin"));
+        assert!(outside.is_none());
+    }
+
+    #[test]
+    fn render_relative_to_repo() {
+        let segment = PathSegment::new_from_path(
+            PathType::RelativeToRepo(PathBuf::from("/home/me/rps")),
+            Cow::Owned(PathBuf::from("src/bin")),
+            PathConfig::default(),
+        );
+        assert_eq!(segment.preferred_width, " rps > src > bin ".len());
+        assert_eq!(
+            segment.render_at_size(segment.preferred_width).text,
+            format!(
+                " {}rps{} {2} src {2} bin ",
+                REPO_NAME_START, REPO_NAME_END, PATH_SEPARATOR
+            )
+        );
+
+        let root = PathSegment::new_from_path(
+            PathType::RelativeToRepo(PathBuf::from("/home/me/rps")),
+            Cow::Owned(PathBuf::new()),
+            PathConfig::default(),
+        );
+        assert_eq!(
+            root.render_at_size(root.preferred_width).text,
+            format!(" {}rps{} ", REPO_NAME_START, REPO_NAME_END)
+        );
+    }
+
+    #[test]
+    fn repo_name_is_never_truncated() {
+        let segment = PathSegment::new_from_path(
+            PathType::RelativeToRepo(PathBuf::from("/home/me/rps")),
+            Cow::Owned(PathBuf::from("src/segments/path")),
+            PathConfig::default(),
+        );
+        assert_eq!(segment.get_actual_width_when_under(14), 14);
+        assert_eq!(
+            segment.render_at_size(14).text,
+            format!(
+                " {}rps{} {} ...ath ",
+                REPO_NAME_START, REPO_NAME_END, PATH_SEPARATOR
+            )
+        );
+        assert_eq!(
+            segment.get_base_width(ShrinkPriority::ShrinkComfortable),
+            12
+        );
+        assert_eq!(segment.render_at_size(11).text, " ");
+    }
+
+    #[test]
+    fn longest_alias_wins() {
+        let aliased = |alias: &str, target: &str| PathType::Aliased {
+            alias: alias.to_string(),
+            target: PathBuf::from(target),
+        };
+        let candidates = || {
+            vec![
+                (PathType::RelativeToHome, PathBuf::from("/home/me")),
+                (
+                    aliased("W", "/home/me/work/monorepo"),
+                    PathBuf::from("/home/me/work/monorepo"),
+                ),
+                (aliased("DATA", "/mnt/data"), PathBuf::from("/mnt/data")),
+            ]
+        };
+        assert_eq!(
+            get_aliased_path(Path::new("/home/me/work/monorepo/src"), candidates()),
+            Some((aliased("W", "/home/me/work/monorepo"), PathBuf::from("src")))
+        );
+        assert_eq!(
+            get_aliased_path(Path::new("/home/me/work"), candidates()),
+            Some((PathType::RelativeToHome, PathBuf::from("work")))
+        );
+        assert_eq!(
+            get_aliased_path(Path::new("/mnt/data"), candidates()),
+            Some((aliased("DATA", "/mnt/data"), PathBuf::new()))
+        );
+        assert_eq!(
+            get_aliased_path(Path::new("/mnt/database"), candidates()),
+            None
+        );
+    }
+
+    #[test]
+    fn alias_is_never_truncated() {
+        let segment = PathSegment::new_from_path(
+            PathType::Aliased {
+                alias: String::from("DATA"),
+                target: PathBuf::from("/mnt/data"),
+            },
+            Cow::Owned(PathBuf::from("photos/2023")),
+            PathConfig::default(),
+        );
+        assert_eq!(
+            segment.render_at_size(segment.preferred_width).text,
+            format!(" DATA {0} photos {0} 2023 ", PATH_SEPARATOR)
+        );
+        assert_eq!(segment.get_actual_width_when_under(15), 15);
+        assert_eq!(
+            segment.render_at_size(15).text,
+            format!(" DATA {0} ...023 ", PATH_SEPARATOR)
+        );
+        // The alias and the shortest truncated path don't fit
+        assert_eq!(
+            segment.get_base_width(ShrinkPriority::ShrinkComfortable),
+            13
+        );
+        assert_eq!(segment.render_at_size(12).text, " ");
+    }
+
+    #[test]
+    fn deleted_directory_uses_pwd() {
+        let dir = std::env::temp_dir().join(format!("rps-path-deleted-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(
+            get_last_known_path(Some(dir.clone()), None),
+            Some((dir.clone(), false))
+        );
+        assert!(is_writable(&dir));
+        std::fs::remove_dir(&dir).unwrap();
+
+        // current_dir fails on Linux once the directory is gone, and returns
+        // the old path elsewhere
+        assert_eq!(
+            get_last_known_path(None, Some(dir.clone())),
+            Some((dir.clone(), true))
+        );
+        assert_eq!(
+            get_last_known_path(Some(dir.clone()), Some(dir.clone())),
+            Some((dir.clone(), true))
+        );
+        assert_eq!(get_last_known_path(None, None), None);
+        assert_eq!(get_last_known_path(None, Some(PathBuf::from("foo"))), None);
+
+        // Recreated at the same path, but the shell is still in the old one
+        std::fs::create_dir(&dir).unwrap();
+        let recreated = get_last_known_path(None, Some(dir.clone()));
+        std::fs::remove_dir(&dir).unwrap();
+        assert_eq!(recreated, Some((dir, true)));
+    }
+
+    #[test]
+    fn render_read_only_and_deleted() {
+        let mut segment = PathSegment::new_from_path(
+            PathType::RelativeToRoot,
+            Cow::Owned(PathBuf::from("usr/share")),
+            PathConfig::default(),
+        );
+        segment.state = DirState::ReadOnly;
+        let width = segment.get_base_width(ShrinkPriority::Unconstrained);
+        assert_eq!(width, segment.preferred_width + 2);
+        assert_eq!(
+            segment.render_at_size(width).text,
+            format!(
+                " / {0} usr {0} share {1} ",
+                PATH_SEPARATOR, READ_ONLY_SYMBOL
+            )
+        );
+        assert_eq!(segment.get_actual_width_when_under(10), 10);
+        assert_eq!(
+            segment.render_at_size(10).text,
+            format!(" ...are {} ", READ_ONLY_SYMBOL)
+        );
+
+        segment.state = DirState::Deleted;
+        let rendered = segment.render_at_size(segment.preferred_width);
+        assert_eq!(
+            rendered.text,
+            format!(" / {0} usr {0} share ", PATH_SEPARATOR)
+        );
+        assert_eq!(rendered.bg_color, colors::RED);
+    }
+
+    #[test]
+    fn components_are_linked() {
+        let mut segment = PathSegment::new_from_path(
+            PathType::RelativeToRoot,
+            Cow::Owned(PathBuf::from("tmp/my dir")),
+            PathConfig::default(),
+        );
+        segment.urls = directory_urls("box", Path::new("/"), &segment.path_segments);
+        let link = |url: &str, text: &str| format!("\x1b]8;;{}\x07{}\x1b]8;;\x07", url, text);
+        assert_eq!(
+            segment.render_at_size(segment.preferred_width).text,
+            format!(
+                " {} {3} {} {3} {} ",
+                link("file://box/", "/"),
+                link("file://box/tmp", "tmp"),
+                link("file://box/tmp/my%20dir", "my dir"),
+                PATH_SEPARATOR
+            )
+        );
+        assert_eq!(
+            segment.render_at_size(9).text,
+            link("file://box/tmp/my%20dir", " ... dir ")
+        );
+    }
+
+    #[test]
+    fn wide_characters() {
+        let segment = PathSegment::new_from_path(
+            PathType::RelativeToHome,
+            Cow::Owned(PathBuf::from("日本語/src")),
+            PathConfig::default(),
+        );
+        assert_eq!(segment.preferred_width, 18);
+        // The 本 would only half fit, so it is replaced by a space
+        assert_eq!(
+            segment.render_at_size(14).text,
+            format!(" ... 語 {} src ", PATH_SEPARATOR)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn rendered_width_matches_reported(
+            components in prop::collection::vec("[a-zé日本🦀]{1,8}", 0..5),
+            abbreviate in any::<bool>(),
+            max_size in 0usize..60,
+        ) {
+            let options = PathConfig {
+                strategy: if abbreviate {
+                    PathStrategy::Abbreviate
+                } else {
+                    PathStrategy::Truncate
+                },
+                ..PathConfig::default()
+            };
+            let path: PathBuf = components.iter().collect();
+            let segment =
+                PathSegment::new_from_path(PathType::RelativeToHome, Cow::Owned(path), options);
+            let reported = segment.get_actual_width_when_under(max_size);
+            prop_assert_eq!(width(&segment.render_at_size(max_size).text), reported);
+            prop_assert!(reported <= max_size.max(1));
+        }
+    }
 }
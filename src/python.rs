@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use crate::{
+    colors,
+    segments::{Context, TieredSegment},
+};
+
+const PYTHON_SYMBOL: char = '\u{1F40D}';
+
+/// The active virtualenv or conda environment
+pub struct PythonEnvSegment {
+    tiers: Vec<String>,
+}
+
+/// The Python version recorded in a virtualenv's `pyvenv.cfg`, which `venv`
+/// writes as `version` and virtualenv as `version_info`
+fn read_pyvenv_version(prefix: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(prefix.join("pyvenv.cfg")).ok()?;
+    text.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        match key.trim() {
+            "version" | "version_info" => {
+                let parts: Vec<&str> = value.trim().split('.').take(3).collect();
+                Some(parts.join("."))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// The Python version installed in a conda environment, from the name of its
+/// package record, like `conda-meta/python-3.11.4-h955ad1f_0.json`
+fn read_conda_version(prefix: &Path) -> Option<String> {
+    std::fs::read_dir(prefix.join("conda-meta"))
+        .ok()?
+        .filter_map(|x| x.ok())
+        .find_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let rest = name.strip_prefix("python-")?;
+            let version = rest.split('-').next()?;
+            version
+                .starts_with(|c: char| c.is_ascii_digit())
+                .then(|| version.to_string())
+        })
+}
+
+/// The name and prefix of the active environment, and whether it is a conda
+/// one. A virtualenv wins, since it is usually activated on top of conda.
+fn get_env(
+    virtual_env: Option<String>,
+    virtual_env_prompt: Option<String>,
+    conda_env: Option<String>,
+    conda_prefix: Option<String>,
+) -> Option<(String, Option<String>, bool)> {
+    let base_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+    };
+
+    if let Some(virtual_env) = virtual_env.filter(|x| !x.is_empty()) {
+        // Older versions of venv put the name in parentheses
+        let name = virtual_env_prompt
+            .map(|x| {
+                x.trim()
+                    .trim_start_matches('(')
+                    .trim_end_matches(')')
+                    .to_string()
+            })
+            .filter(|x| !x.is_empty())
+            .or_else(|| base_name(&virtual_env))?;
+        return Some((name, Some(virtual_env), false));
+    }
+
+    let conda_prefix = conda_prefix.filter(|x| !x.is_empty());
+    let name = conda_env
+        .filter(|x| !x.is_empty())
+        .or_else(|| base_name(conda_prefix.as_ref()?))?;
+    Some((name, conda_prefix, true))
+}
+
+impl PythonEnvSegment {
+    pub fn new(context: &Context) -> Option<Self> {
+        let var = |name| std::env::var(name).ok();
+        let (name, prefix, conda) = get_env(
+            var("VIRTUAL_ENV"),
+            var("VIRTUAL_ENV_PROMPT"),
+            var("CONDA_DEFAULT_ENV"),
+            var("CONDA_PREFIX"),
+        )?;
+        let version = match prefix {
+            Some(prefix) if context.config.python.show_version => {
+                if conda {
+                    read_conda_version(Path::new(&prefix))
+                } else {
+                    read_pyvenv_version(Path::new(&prefix))
+                }
+            }
+            _ => None,
+        };
+        Some(Self::new_from_env(&name, version.as_deref()))
+    }
+
+    fn new_from_env(name: &str, version: Option<&str>) -> Self {
+        let mut tiers = Vec::new();
+        if let Some(version) = version {
+            tiers.push(format!(" {} {} {} ", PYTHON_SYMBOL, name, version));
+        }
+        tiers.push(format!(" {} {} ", PYTHON_SYMBOL, name));
+        tiers.push(format!(" {} ", PYTHON_SYMBOL));
+        PythonEnvSegment { tiers }
+    }
+}
+
+impl TieredSegment for PythonEnvSegment {
+    fn tiers(&self) -> &[String] {
+        &self.tiers
+    }
+
+    fn colors(&self) -> (colors::Color, colors::Color) {
+        (colors::BLACK, colors::BLUE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::segments::{PromptSegment, ShrinkPriority};
+
+    use super::{get_env, read_conda_version, read_pyvenv_version, PythonEnvSegment};
+
+    fn some(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn env_names() {
+        assert_eq!(
+            get_env(some("/home/me/proj/.venv"), some("(proj) "), None, None),
+            Some((String::from("proj"), some("/home/me/proj/.venv"), false))
+        );
+        assert_eq!(
+            get_env(some("/home/me/proj/.venv"), None, some("base"), None),
+            Some((String::from(".venv"), some("/home/me/proj/.venv"), false))
+        );
+        assert_eq!(
+            get_env(None, None, some("torch"), some("/opt/conda/envs/torch")),
+            Some((String::from("torch"), some("/opt/conda/envs/torch"), true))
+        );
+        assert_eq!(
+            get_env(None, None, None, some("/opt/conda/envs/torch")),
+            Some((String::from("torch"), some("/opt/conda/envs/torch"), true))
+        );
+        assert_eq!(get_env(some(""), None, None, None), None);
+    }
+
+    #[test]
+    fn read_versions() {
+        let dir = std::env::temp_dir().join(format!("rps-python-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("venv")).unwrap();
+        std::fs::create_dir_all(dir.join("virtualenv")).unwrap();
+        std::fs::create_dir_all(dir.join("conda/conda-meta")).unwrap();
+        std::fs::write(
+            dir.join("venv/pyvenv.cfg"),
+            "home = /usr/bin\ninclude-system-site-packages = false\nversion = 3.11.4\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("virtualenv/pyvenv.cfg"),
+            "home = /usr/bin\nversion_info = 3.12.1.final.0\n",
+        )
+        .unwrap();
+        for name in [
+            "python-dateutil-2.8.2-pyhd3eb1b0_0.json",
+            "python-3.10.13-h955ad1f_0.json",
+        ] {
+            std::fs::write(dir.join("conda/conda-meta").join(name), "{}").unwrap();
+        }
+
+        let venv = read_pyvenv_version(&dir.join("venv"));
+        let virtualenv = read_pyvenv_version(&dir.join("virtualenv"));
+        let conda = read_conda_version(&dir.join("conda"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(venv.as_deref(), Some("3.11.4"));
+        assert_eq!(virtualenv.as_deref(), Some("3.12.1"));
+        assert_eq!(conda.as_deref(), Some("3.10.13"));
+        assert_eq!(read_pyvenv_version(Path::new("/nonexistent")), None);
+    }
+
+    #[test]
+    fn shrinks_to_name_then_symbol() {
+        let segment = PythonEnvSegment::new_from_env("proj", Some("3.11.4"));
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 16);
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 9);
+        assert_eq!(segment.render_at_size(16).text, " \u{1F40D} proj 3.11.4 ");
+        assert_eq!(segment.get_actual_width_when_under(15), 9);
+        assert_eq!(segment.render_at_size(15).text, " \u{1F40D} proj ");
+        assert_eq!(segment.render_at_size(5).text, " \u{1F40D} ");
+        assert_eq!(segment.render_at_size(3).text, "");
+
+        let segment = PythonEnvSegment::new_from_env("proj", None);
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 9);
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 9);
+    }
+}
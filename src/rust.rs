@@ -0,0 +1,172 @@
+use std::path::Path;
+
+use crate::{
+    colors,
+    segments::{Context, TieredSegment},
+};
+
+const RUST_SYMBOL: char = '\u{1F980}';
+
+/// The crate the working directory is in and the toolchain it builds with,
+/// all read from files so that nothing has to run `rustc`
+pub struct RustSegment {
+    tiers: Vec<String>,
+}
+
+/// The name and version from the `[package]` table of a `Cargo.toml`, which
+/// a virtual workspace doesn't have
+fn read_package(manifest: &str) -> (Option<String>, Option<String>) {
+    let Ok(table) = manifest.parse::<toml::Table>() else {
+        return (None, None);
+    };
+    let field = |key: &str| {
+        let value = table.get("package")?.get(key)?;
+        // `version.workspace = true` is a table
+        value.as_str().map(String::from)
+    };
+    (field("name"), field("version"))
+}
+
+/// The channel from a `rust-toolchain.toml`, or from a `rust-toolchain` file
+/// in either its TOML or its older one-line form
+fn parse_toolchain(text: &str) -> Option<String> {
+    if let Ok(table) = text.parse::<toml::Table>() {
+        return table
+            .get("toolchain")?
+            .get("channel")?
+            .as_str()
+            .map(String::from);
+    }
+    let line = text.lines().next()?.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+/// The toolchain pinned by the nearest toolchain file, looking where rustup
+/// does. A directory's `rust-toolchain` wins over its `rust-toolchain.toml`.
+fn find_toolchain(dir: &Path) -> Option<String> {
+    dir.ancestors().find_map(|dir| {
+        ["rust-toolchain", "rust-toolchain.toml"]
+            .into_iter()
+            .find_map(|name| std::fs::read_to_string(dir.join(name)).ok())
+            .and_then(|x| parse_toolchain(&x))
+    })
+}
+
+impl RustSegment {
+    pub fn new(context: &Context) -> Option<Self> {
+        let cwd = context.path.as_ref()?;
+        let manifest = cwd
+            .ancestors()
+            .find_map(|x| std::fs::read_to_string(x.join("Cargo.toml")).ok())?;
+        let (name, version) = read_package(&manifest);
+        let toolchain = find_toolchain(cwd).or_else(|| std::env::var("RUSTUP_TOOLCHAIN").ok());
+        Some(Self::new_from_parts(
+            name.as_deref(),
+            version.as_deref(),
+            toolchain.as_deref(),
+        ))
+    }
+
+    fn new_from_parts(name: Option<&str>, version: Option<&str>, toolchain: Option<&str>) -> Self {
+        let text = |parts: &[Option<&str>]| {
+            let parts: Vec<&str> = parts.iter().flatten().copied().collect();
+            if parts.is_empty() {
+                format!(" {} ", RUST_SYMBOL)
+            } else {
+                format!(" {} {} ", RUST_SYMBOL, parts.join(" "))
+            }
+        };
+        let mut tiers = vec![
+            text(&[name, version, toolchain]),
+            text(&[name, toolchain]),
+            text(&[name.or(toolchain)]),
+            text(&[]),
+        ];
+        tiers.dedup();
+        RustSegment { tiers }
+    }
+}
+
+impl TieredSegment for RustSegment {
+    fn tiers(&self) -> &[String] {
+        &self.tiers
+    }
+
+    fn colors(&self) -> (colors::Color, colors::Color) {
+        (colors::BLACK, colors::RED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::segments::{PromptSegment, ShrinkPriority};
+
+    use super::{find_toolchain, parse_toolchain, read_package, RustSegment};
+
+    #[test]
+    fn package_name_and_version() {
+        assert_eq!(
+            read_package("[package]\nname = \"rps\"\nversion = \"0.1.0\"\n"),
+            (Some(String::from("rps")), Some(String::from("0.1.0")))
+        );
+        assert_eq!(
+            read_package("[package]\nname = \"rps\"\nversion.workspace = true\n"),
+            (Some(String::from("rps")), None)
+        );
+        assert_eq!(
+            read_package("[workspace]\nmembers = [\"a\"]\n"),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn toolchain_files() {
+        assert_eq!(
+            parse_toolchain("[toolchain]\nchannel = \"nightly-2023-12-28\"\n").as_deref(),
+            Some("nightly-2023-12-28")
+        );
+        assert_eq!(parse_toolchain("1.75.0\n").as_deref(), Some("1.75.0"));
+        assert_eq!(parse_toolchain("[toolchain]\npath = \"/opt/rust\"\n"), None);
+        assert_eq!(parse_toolchain(""), None);
+    }
+
+    #[test]
+    fn finds_toolchain_in_ancestors() {
+        let dir = std::env::temp_dir().join(format!("rps-rust-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("crate/src")).unwrap();
+        std::fs::write(
+            dir.join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"stable\"\n",
+        )
+        .unwrap();
+        let from_toml = find_toolchain(&dir.join("crate/src"));
+        std::fs::write(dir.join("crate/rust-toolchain"), "nightly\n").unwrap();
+        let from_legacy = find_toolchain(&dir.join("crate/src"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(from_toml.as_deref(), Some("stable"));
+        assert_eq!(from_legacy.as_deref(), Some("nightly"));
+    }
+
+    #[test]
+    fn shrinks_to_name_then_symbol() {
+        let segment = RustSegment::new_from_parts(Some("rps"), Some("0.1.0"), Some("stable"));
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 21);
+        assert_eq!(
+            segment.render_at_size(21).text,
+            " \u{1F980} rps 0.1.0 stable "
+        );
+        assert_eq!(segment.render_at_size(20).text, " \u{1F980} rps stable ");
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 8);
+        assert_eq!(segment.render_at_size(10).text, " \u{1F980} rps ");
+        assert_eq!(segment.render_at_size(5).text, " \u{1F980} ");
+        assert_eq!(segment.render_at_size(3).text, "");
+
+        let workspace = RustSegment::new_from_parts(None, None, Some("stable"));
+        assert_eq!(workspace.render_at_size(20).text, " \u{1F980} stable ");
+        assert_eq!(
+            workspace.get_base_width(ShrinkPriority::ShrinkComfortable),
+            11
+        );
+    }
+}
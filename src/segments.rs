@@ -1,11 +1,33 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use crate::colors;
+use crate::{colors, config::Config, width::width};
 
 pub struct Context {
     pub path: Option<PathBuf>,
     pub pipestatus: Option<String>,
-    pub jobs: usize,
+    pub running_jobs: usize,
+    pub stopped_jobs: usize,
+    /// How long the last command took
+    pub duration: Option<Duration>,
+    /// Names the shell uses for directories, like zsh's `hash -d`
+    pub named_dirs: Vec<(String, PathBuf)>,
+    pub config: Config,
+}
+
+#[cfg(test)]
+impl Context {
+    /// A context with nothing set, for tests to fill in what they need
+    pub fn for_test() -> Self {
+        Context {
+            path: None,
+            pipestatus: None,
+            running_jobs: 0,
+            stopped_jobs: 0,
+            duration: None,
+            named_dirs: Vec::new(),
+            config: Config::default(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -21,8 +43,81 @@ pub struct RenderedSegment {
     pub fg_color: colors::Color,
 }
 
-pub trait PromptSegment {
+/// Segments are built on their own threads and handed back to the main one
+pub trait PromptSegment: Send {
     fn get_base_width(&self, shrink: ShrinkPriority) -> usize;
     fn get_actual_width_when_under(&self, max_size: usize) -> usize;
     fn render_at_size(&self, max_size: usize) -> RenderedSegment;
 }
+
+/// A segment that shrinks by switching to shorter text, like from `user@host`
+/// to `host` to an icon, and is left out when not even the last one fits
+pub trait TieredSegment: Send {
+    /// Text for each size the segment can shrink to, from largest to smallest
+    fn tiers(&self) -> &[String];
+    /// The background and foreground colors
+    fn colors(&self) -> (colors::Color, colors::Color);
+}
+
+fn tier_under(tiers: &[String], max_size: usize) -> Option<&String> {
+    tiers.iter().find(|x| width(x) <= max_size)
+}
+
+impl<T: TieredSegment> PromptSegment for T {
+    fn get_base_width(&self, shrink: ShrinkPriority) -> usize {
+        let tiers = self.tiers();
+        match shrink {
+            ShrinkPriority::Unconstrained => width(&tiers[0]),
+            // The smallest tier with more than just the icon
+            ShrinkPriority::ShrinkComfortable => width(&tiers[tiers.len().saturating_sub(2)]),
+            ShrinkPriority::ShrinkBeyondMin => 0,
+        }
+    }
+
+    fn get_actual_width_when_under(&self, max_size: usize) -> usize {
+        tier_under(self.tiers(), max_size).map_or(0, |x| width(x))
+    }
+
+    fn render_at_size(&self, max_size: usize) -> RenderedSegment {
+        let (bg_color, fg_color) = self.colors();
+        RenderedSegment {
+            text: tier_under(self.tiers(), max_size)
+                .cloned()
+                .unwrap_or_default(),
+            bg_color,
+            fg_color,
+        }
+    }
+}
+
+/// Stands in for a segment that took too long to build
+pub struct TimedOutSegment;
+
+impl PromptSegment for TimedOutSegment {
+    fn get_base_width(&self, shrink: ShrinkPriority) -> usize {
+        match shrink {
+            ShrinkPriority::Unconstrained | ShrinkPriority::ShrinkComfortable => 3,
+            ShrinkPriority::ShrinkBeyondMin => 0,
+        }
+    }
+
+    fn get_actual_width_when_under(&self, max_size: usize) -> usize {
+        if max_size >= 3 {
+            3
+        } else {
+            0
+        }
+    }
+
+    fn render_at_size(&self, max_size: usize) -> RenderedSegment {
+        RenderedSegment {
+            text: if max_size >= 3 {
+                String::from(" \u{29D6} ")
+            } else {
+                String::new()
+            },
+            bg_color: colors::WHITE,
+            fg_color: colors::BLACK,
+        }
+    }
+}
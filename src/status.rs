@@ -1,13 +1,24 @@
 use crate::{
     colors,
     segments::{Context, PromptSegment, RenderedSegment, ShrinkPriority},
+    width::width,
 };
-use const_format::formatcp;
 
 #[derive(Debug, PartialEq, Eq)]
 enum ExitStatus {
     Ok,
-    Failed,
+    Failed(i32),
+}
+
+/// How much to show for each failed command, from most to least detailed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StatusDetail {
+    /// The code and what it means, like `✘ 130 INT`
+    Full,
+    /// Just the code
+    Codes,
+    /// Just the symbol
+    Symbols,
 }
 
 pub struct StatusSegment {
@@ -15,55 +26,126 @@ pub struct StatusSegment {
 }
 
 impl StatusSegment {
+    /// Codes can be negative, like `$LASTEXITCODE` in pwsh. Anything that
+    /// isn't a number is left out rather than guessed at.
     pub fn new(context: &Context) -> Option<Self> {
-        let statuses = context.pipestatus.as_ref()?;
-        let all_ok = statuses.split_ascii_whitespace().all(|x| x == "0");
-        if all_ok {
+        let status: Vec<ExitStatus> = context
+            .pipestatus
+            .as_ref()?
+            .split_ascii_whitespace()
+            .filter_map(|x| x.parse::<i32>().ok())
+            .map(|code| match code {
+                0 => ExitStatus::Ok,
+                code => ExitStatus::Failed(code),
+            })
+            .collect();
+        if status.iter().all(|x| *x == ExitStatus::Ok) {
             None
         } else {
-            Some(StatusSegment {
-                status: statuses
-                    .split_ascii_whitespace()
-                    .map(|x| {
-                        if x == "0" {
-                            ExitStatus::Ok
-                        } else {
-                            ExitStatus::Failed
-                        }
-                    })
-                    .collect(),
-            })
+            Some(StatusSegment { status })
         }
     }
 
-    fn get_unconstrained_size(&self) -> usize {
-        self.status.len() * 2 + 1
+    fn get_size(&self, detail: StatusDetail) -> usize {
+        let text_len: usize = self
+            .status
+            .iter()
+            .map(|x| width(&status_text(x, detail)))
+            .sum();
+        text_len + self.status.len() + 1
     }
 }
 
-const SUCCESS_SYMBOL: &str = formatcp!("\x1b[{}m\u{2713}", colors::GREEN.fg);
-const FAILURE_SYMBOL: &str = formatcp!("\x1b[{}m\u{2718}", colors::RED.fg);
+const SUCCESS_SYMBOL: char = '\u{2713}';
+pub const FAILURE_SYMBOL: char = '\u{2718}';
+
+/// (signal number, name without the SIG prefix)
+const SIGNALS: [(i32, &str); 29] = [
+    (libc::SIGHUP, "HUP"),
+    (libc::SIGINT, "INT"),
+    (libc::SIGQUIT, "QUIT"),
+    (libc::SIGILL, "ILL"),
+    (libc::SIGTRAP, "TRAP"),
+    (libc::SIGABRT, "ABRT"),
+    (libc::SIGBUS, "BUS"),
+    (libc::SIGFPE, "FPE"),
+    (libc::SIGKILL, "KILL"),
+    (libc::SIGUSR1, "USR1"),
+    (libc::SIGSEGV, "SEGV"),
+    (libc::SIGUSR2, "USR2"),
+    (libc::SIGPIPE, "PIPE"),
+    (libc::SIGALRM, "ALRM"),
+    (libc::SIGTERM, "TERM"),
+    (libc::SIGCHLD, "CHLD"),
+    (libc::SIGCONT, "CONT"),
+    (libc::SIGSTOP, "STOP"),
+    (libc::SIGTSTP, "TSTP"),
+    (libc::SIGTTIN, "TTIN"),
+    (libc::SIGTTOU, "TTOU"),
+    (libc::SIGURG, "URG"),
+    (libc::SIGXCPU, "XCPU"),
+    (libc::SIGXFSZ, "XFSZ"),
+    (libc::SIGVTALRM, "VTALRM"),
+    (libc::SIGPROF, "PROF"),
+    (libc::SIGWINCH, "WINCH"),
+    (libc::SIGIO, "IO"),
+    (libc::SIGSYS, "SYS"),
+];
 
-fn render_status(status: &ExitStatus) -> &'static str {
-    match status {
-        ExitStatus::Ok => SUCCESS_SYMBOL,
-        ExitStatus::Failed => FAILURE_SYMBOL,
+/// What an exit code means, for the codes shells give a meaning to
+fn describe_code(code: i32) -> Option<&'static str> {
+    match code {
+        126 => Some("NOEXEC"),
+        127 => Some("NOTFOUND"),
+        // Shells report a command killed by a signal as 128 + the signal
+        129.. => SIGNALS
+            .iter()
+            .find(|(signal, _)| code - 128 == *signal)
+            .map(|(_, name)| *name),
+        _ => None,
     }
 }
 
+fn status_text(status: &ExitStatus, detail: StatusDetail) -> String {
+    match (status, detail) {
+        (ExitStatus::Ok, _) => SUCCESS_SYMBOL.to_string(),
+        (ExitStatus::Failed(_), StatusDetail::Symbols) => FAILURE_SYMBOL.to_string(),
+        (ExitStatus::Failed(code), StatusDetail::Codes) => format!("{} {}", FAILURE_SYMBOL, code),
+        (ExitStatus::Failed(code), StatusDetail::Full) => match describe_code(*code) {
+            Some(meaning) => format!("{} {} {}", FAILURE_SYMBOL, code, meaning),
+            None => format!("{} {}", FAILURE_SYMBOL, code),
+        },
+    }
+}
+
+fn render_status(status: &ExitStatus, detail: StatusDetail) -> String {
+    let color = match status {
+        ExitStatus::Ok => colors::GREEN,
+        ExitStatus::Failed(_) => colors::RED,
+    };
+    format!("{}{}", color.fg_sequence(), status_text(status, detail))
+}
+
 impl PromptSegment for StatusSegment {
     fn get_base_width(&self, shrink: crate::segments::ShrinkPriority) -> usize {
         match shrink {
-            ShrinkPriority::Unconstrained => self.get_unconstrained_size(),
-            ShrinkPriority::ShrinkComfortable => 3,
+            ShrinkPriority::Unconstrained => self.get_size(StatusDetail::Full),
+            ShrinkPriority::ShrinkComfortable => self.get_size(StatusDetail::Symbols),
             ShrinkPriority::ShrinkBeyondMin => 0,
         }
     }
 
     fn get_actual_width_when_under(&self, max_size: usize) -> usize {
-        if max_size >= self.get_unconstrained_size() {
-            self.get_unconstrained_size()
-        } else if max_size >= 3 {
+        for detail in [
+            StatusDetail::Full,
+            StatusDetail::Codes,
+            StatusDetail::Symbols,
+        ] {
+            if max_size >= self.get_size(detail) {
+                return self.get_size(detail);
+            }
+        }
+        if max_size >= 3 {
             3
         } else {
             0
@@ -71,20 +153,32 @@ impl PromptSegment for StatusSegment {
     }
 
     fn render_at_size(&self, max_size: usize) -> RenderedSegment {
-        let text = if max_size >= self.get_unconstrained_size() {
-            self.status
-                .iter()
-                .map(render_status)
-                .intersperse(" ")
-                .collect::<String>()
-        } else if max_size >= 3 {
-            render_status(&self.status[0]).to_string()
-        } else {
-            String::new()
+        let detail = [
+            StatusDetail::Full,
+            StatusDetail::Codes,
+            StatusDetail::Symbols,
+        ]
+        .into_iter()
+        .find(|x| max_size >= self.get_size(*x));
+        let text = match detail {
+            Some(detail) => format!(
+                " {} ",
+                self.status
+                    .iter()
+                    .map(|x| render_status(x, detail))
+                    .intersperse(String::from(" "))
+                    .collect::<String>()
+            ),
+            // Only room to say that something failed
+            None if max_size >= 3 => format!(
+                " {} ",
+                render_status(&ExitStatus::Failed(1), StatusDetail::Symbols)
+            ),
+            None => String::new(),
         };
 
         RenderedSegment {
-            text: format!(" {} ", text),
+            text,
             bg_color: colors::BLACK,
             fg_color: colors::BLACK,
         }
@@ -93,21 +187,85 @@ impl PromptSegment for StatusSegment {
 
 #[cfg(test)]
 mod tests {
-    use crate::{segments::Context, status::ExitStatus};
+    use crate::{
+        colors,
+        segments::{Context, PromptSegment, ShrinkPriority},
+        status::ExitStatus,
+    };
+
+    use super::{describe_code, StatusSegment};
 
-    use super::StatusSegment;
+    fn segment_for(pipestatus: &str) -> StatusSegment {
+        let context = Context {
+            pipestatus: Some(String::from(pipestatus)),
+            ..Context::for_test()
+        };
+        StatusSegment::new(&context).unwrap()
+    }
+
+    /// Text without the color sequences
+    fn plain(text: &str) -> String {
+        text.replace(&colors::GREEN.fg_sequence(), "")
+            .replace(&colors::RED.fg_sequence(), "")
+    }
 
     #[test]
     fn create_segment() {
         let context = Context {
-            path: None,
             pipestatus: Some(String::from("0 127 0")),
-            jobs: 0,
+            ..Context::for_test()
         };
         let segment = StatusSegment::new(&context).unwrap();
         assert_eq!(segment.status.len(), 3);
         assert_eq!(segment.status[0], ExitStatus::Ok);
-        assert_eq!(segment.status[1], ExitStatus::Failed);
+        assert_eq!(segment.status[1], ExitStatus::Failed(127));
         assert_eq!(segment.status[2], ExitStatus::Ok);
     }
+
+    #[test]
+    fn describe_codes() {
+        assert_eq!(describe_code(1), None);
+        assert_eq!(describe_code(126), Some("NOEXEC"));
+        assert_eq!(describe_code(127), Some("NOTFOUND"));
+        assert_eq!(describe_code(128), None);
+        assert_eq!(describe_code(130), Some("INT"));
+        assert_eq!(describe_code(137), Some("KILL"));
+        assert_eq!(describe_code(139), Some("SEGV"));
+        assert_eq!(describe_code(255), None);
+        assert_eq!(describe_code(-1), None);
+    }
+
+    #[test]
+    fn render_tiers() {
+        let segment = segment_for("0 130");
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 13);
+        assert_eq!(segment.get_base_width(ShrinkPriority::ShrinkComfortable), 5);
+        assert_eq!(plain(&segment.render_at_size(20).text), " ✓ ✘ 130 INT ");
+        assert_eq!(segment.get_actual_width_when_under(12), 9);
+        assert_eq!(plain(&segment.render_at_size(12).text), " ✓ ✘ 130 ");
+        assert_eq!(plain(&segment.render_at_size(8).text), " ✓ ✘ ");
+        assert_eq!(segment.get_actual_width_when_under(4), 3);
+        assert_eq!(plain(&segment.render_at_size(4).text), " ✘ ");
+        assert_eq!(segment.render_at_size(2).text, "");
+    }
+
+    #[test]
+    fn render_code_without_meaning() {
+        let segment = segment_for("2");
+        assert_eq!(segment.get_base_width(ShrinkPriority::Unconstrained), 5);
+        assert_eq!(plain(&segment.render_at_size(5).text), " ✘ 2 ");
+    }
+
+    #[test]
+    fn negative_codes_and_junk() {
+        let segment = segment_for("-1 x 0");
+        assert_eq!(segment.status, [ExitStatus::Failed(-1), ExitStatus::Ok]);
+        assert_eq!(plain(&segment.render_at_size(20).text), " ✘ -1 ✓ ");
+
+        let context = Context {
+            pipestatus: Some(String::from("0 ?")),
+            ..Context::for_test()
+        };
+        assert!(StatusSegment::new(&context).is_none());
+    }
 }
@@ -0,0 +1,146 @@
+use crate::colors::Color;
+
+/// Maps the 16 basic colors that segments use onto the colors actually shown
+pub struct Theme {
+    pub name: &'static str,
+    palette: [Color; 16],
+}
+
+impl Theme {
+    pub fn resolve(&self, color: Color) -> Color {
+        match color {
+            Color::Ansi(n) => self.palette[(n % 16) as usize],
+            color => color,
+        }
+    }
+}
+
+const fn hex(rgb: u32) -> Color {
+    Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
+/// Uses the terminal's own palette
+pub const DEFAULT: Theme = Theme {
+    name: "default",
+    palette: [
+        Color::Ansi(0),
+        Color::Ansi(1),
+        Color::Ansi(2),
+        Color::Ansi(3),
+        Color::Ansi(4),
+        Color::Ansi(5),
+        Color::Ansi(6),
+        Color::Ansi(7),
+        Color::Ansi(8),
+        Color::Ansi(9),
+        Color::Ansi(10),
+        Color::Ansi(11),
+        Color::Ansi(12),
+        Color::Ansi(13),
+        Color::Ansi(14),
+        Color::Ansi(15),
+    ],
+};
+
+pub const SOLARIZED: Theme = Theme {
+    name: "solarized",
+    palette: [
+        hex(0x073642),
+        hex(0xdc322f),
+        hex(0x859900),
+        hex(0xb58900),
+        hex(0x268bd2),
+        hex(0xd33682),
+        hex(0x2aa198),
+        hex(0xeee8d5),
+        hex(0x002b36),
+        hex(0xcb4b16),
+        hex(0x586e75),
+        hex(0x657b83),
+        hex(0x839496),
+        hex(0x6c71c4),
+        hex(0x93a1a1),
+        hex(0xfdf6e3),
+    ],
+};
+
+pub const GRUVBOX: Theme = Theme {
+    name: "gruvbox",
+    palette: [
+        hex(0x282828),
+        hex(0xcc241d),
+        hex(0x98971a),
+        hex(0xd79921),
+        hex(0x458588),
+        hex(0xb16286),
+        hex(0x689d6a),
+        hex(0xa89984),
+        hex(0x928374),
+        hex(0xfb4934),
+        hex(0xb8bb26),
+        hex(0xfabd2f),
+        hex(0x83a598),
+        hex(0xd3869b),
+        hex(0x8ec07c),
+        hex(0xebdbb2),
+    ],
+};
+
+/// Fully saturated colors on pure black and white
+pub const HIGH_CONTRAST: Theme = Theme {
+    name: "high-contrast",
+    palette: [
+        hex(0x000000),
+        hex(0xff0000),
+        hex(0x00ff00),
+        hex(0xffff00),
+        hex(0x00aaff),
+        hex(0xff00ff),
+        hex(0x00ffff),
+        hex(0xffffff),
+        hex(0x000000),
+        hex(0xff0000),
+        hex(0x00ff00),
+        hex(0xffff00),
+        hex(0x00aaff),
+        hex(0xff00ff),
+        hex(0x00ffff),
+        hex(0xffffff),
+    ],
+};
+
+pub const THEMES: [&Theme; 4] = [&DEFAULT, &SOLARIZED, &GRUVBOX, &HIGH_CONTRAST];
+
+pub fn get_theme(name: &str) -> Option<&'static Theme> {
+    THEMES.into_iter().find(|theme| theme.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::colors::{self, Color};
+
+    use super::{get_theme, DEFAULT, SOLARIZED};
+
+    #[test]
+    fn default_theme_is_identity() {
+        assert_eq!(DEFAULT.resolve(colors::BLUE), colors::BLUE);
+        assert_eq!(DEFAULT.resolve(colors::BRIGHT_RED), colors::BRIGHT_RED);
+    }
+
+    #[test]
+    fn theme_maps_basic_colors() {
+        assert_eq!(
+            SOLARIZED.resolve(colors::BLUE),
+            Color::Rgb(0x26, 0x8b, 0xd2)
+        );
+        assert_eq!(SOLARIZED.resolve(colors::DEFAULT), colors::DEFAULT);
+        assert_eq!(SOLARIZED.resolve(Color::Indexed(4)), Color::Indexed(4));
+    }
+
+    #[test]
+    fn find_theme_by_name() {
+        assert_eq!(get_theme("gruvbox").unwrap().name, "gruvbox");
+        assert_eq!(get_theme("high-contrast").unwrap().name, "high-contrast");
+        assert!(get_theme("nonexistent").is_none());
+    }
+}
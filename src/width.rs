@@ -0,0 +1,91 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::escape::strip_escapes;
+
+/// Terminal cells taken up by one grapheme cluster. Emoji sequences are as
+/// wide as a single emoji, however many code points they are made of.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.contains('\u{FE0F}') {
+        2
+    } else {
+        grapheme.width().min(2)
+    }
+}
+
+/// Terminal cells taken up by `text`, not counting escape sequences
+pub fn width(text: &str) -> usize {
+    strip_escapes(text)
+        .graphemes(true)
+        .map(grapheme_width)
+        .sum()
+}
+
+/// The longest start of `text` that fits in `max_width` cells
+pub fn take_width(text: &str, max_width: usize) -> &str {
+    let mut used = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > max_width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// The longest end of `text` that fits in `max_width` cells
+pub fn take_width_from_end(text: &str, max_width: usize) -> &str {
+    let mut used = 0;
+    for (i, grapheme) in text.grapheme_indices(true).rev() {
+        used += grapheme_width(grapheme);
+        if used > max_width {
+            return &text[i + grapheme.len()..];
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::{grapheme_width, take_width, take_width_from_end, width};
+
+    #[test]
+    fn wide_characters() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("🦀"), 2);
+        assert_eq!(width("👩‍🔬"), 2);
+        assert_eq!(width("❤️"), 2);
+        assert_eq!(width("\x1b[31mred\x1b[0m"), 3);
+    }
+
+    #[test]
+    fn cuts_at_cell_boundaries() {
+        assert_eq!(take_width("日本語", 3), "日");
+        assert_eq!(take_width("日本語", 4), "日本");
+        assert_eq!(take_width("ab", 5), "ab");
+        assert_eq!(take_width_from_end("日本語", 3), "語");
+        assert_eq!(take_width_from_end("a日b", 2), "b");
+        assert_eq!(take_width_from_end("ab", 0), "");
+    }
+
+    proptest! {
+        #[test]
+        fn taken_text_fits(text in "\\PC{0,20}", max_width in 0usize..30) {
+            let start = take_width(&text, max_width);
+            let end = take_width_from_end(&text, max_width);
+            prop_assert!(width(start) <= max_width);
+            prop_assert!(width(end) <= max_width);
+            prop_assert!(text.starts_with(start));
+            prop_assert!(text.ends_with(end));
+            // The next grapheme wouldn't have fit
+            if let Some(next) = text[start.len()..].graphemes(true).next() {
+                prop_assert!(width(start) + grapheme_width(next) > max_width);
+            }
+        }
+    }
+}
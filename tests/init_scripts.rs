@@ -0,0 +1,308 @@
+//! Runs the generated init script in each shell and checks the prompt it
+//! renders. Shells that aren't installed are skipped, unless
+//! `RPS_REQUIRE_SHELLS` is set as it is in CI.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const RPS: &str = env!("CARGO_BIN_EXE_rps");
+
+/// An empty directory to run the shell in, which also holds the config and
+/// runtime directories so the user's own config and daemon aren't used
+fn test_dir(shell: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rps-init-{}-{}", shell, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("config")).unwrap();
+    dir
+}
+
+fn init_script(shell: &str) -> String {
+    init_script_from(Path::new(RPS), shell)
+}
+
+fn init_script_from(exe: &Path, shell: &str) -> String {
+    let output = Command::new(exe).arg("--init").arg(shell).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs `script` with the shell, returning its output, or `None` if the shell
+/// isn't installed and isn't required
+fn run_script(
+    dir: &Path,
+    command: &str,
+    args: &[&str],
+    file_name: &str,
+    script: &str,
+) -> Option<String> {
+    let script_path = dir.join(file_name);
+    fs::write(&script_path, script).unwrap();
+    let result = Command::new(command)
+        .args(args)
+        .arg(&script_path)
+        .current_dir(dir)
+        .env("COLUMNS", "120")
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_RUNTIME_DIR", dir)
+        .output();
+    let _ = fs::remove_dir_all(dir);
+    let output = match result {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if std::env::var_os("RPS_REQUIRE_SHELLS").is_some() {
+                panic!("{} is not installed", command);
+            }
+            eprintln!("{} is not installed, skipping", command);
+            return None;
+        }
+        Err(e) => panic!("running {}: {}", command, e),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "{} failed\nstdout:\n{}\nstderr:\n{}",
+        command,
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(stdout)
+}
+
+fn assert_prompt(prompt: &str, dir: &Path) {
+    let name = dir.file_name().unwrap().to_str().unwrap();
+    assert!(
+        prompt.contains(name),
+        "prompt doesn't show the path: {:?}",
+        prompt
+    );
+}
+
+#[test]
+fn zsh() {
+    let dir = test_dir("zsh");
+    let script = format!(
+        "{}\nfalse\n__rps_precmd\nprint -r -- \"$PS1\"\n",
+        init_script("zsh")
+    );
+    if let Some(prompt) = run_script(&dir, "zsh", &["-f"], "init.zsh", &script) {
+        assert_prompt(&prompt, &dir);
+        assert!(prompt.contains("\u{2718} 1"), "{:?}", prompt);
+    }
+}
+
+#[test]
+fn bash() {
+    let dir = test_dir("bash");
+    let script = format!(
+        "{}\ntrue | false | true\n__rps_prompt_command\nprintf '%s' \"$PS1\"\n",
+        init_script("bash")
+    );
+    if let Some(prompt) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        assert_prompt(&prompt, &dir);
+        // true | false | true
+        assert_eq!(prompt.matches('\u{2713}').count(), 2, "{:?}", prompt);
+        assert!(prompt.contains("\u{2718} 1"), "{:?}", prompt);
+    }
+}
+
+#[test]
+fn bash_keeps_exit_status() {
+    let dir = test_dir("bash-exit-status");
+    let script = format!(
+        "{}\n(exit 3)\n__rps_prompt_command\necho \"status $?\"\n",
+        init_script("bash")
+    );
+    if let Some(output) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        assert!(output.contains("status 3"), "{:?}", output);
+    }
+}
+
+#[test]
+fn bash_shows_expansions_literally() {
+    let dir = test_dir("bash-expansions");
+    fs::create_dir(dir.join("$(touch PWNED)")).unwrap();
+    // ${PS1@P} expands the prompt the way bash does before showing it
+    let script = format!(
+        "if (( BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] < 4 || BASH_VERSINFO[0] < 4 )); then\n\
+            echo old bash\n\
+            exit\n\
+        fi\n\
+        {}\ncd '$(touch PWNED)'\n__rps_prompt_command\nprintf '%s\\n' \"${{PS1@P}}\"\n\
+        if [[ -e PWNED ]]; then echo 'command ran'; fi\n",
+        init_script("bash")
+    );
+    if let Some(output) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        if output.contains("old bash") {
+            return;
+        }
+        assert!(!output.contains("command ran"), "{:?}", output);
+        // bash shows an escaped `$` as `#` for root
+        let shown = ["$(touch PWNED)", "#(touch PWNED)"];
+        assert!(shown.iter().any(|x| output.contains(x)), "{:?}", output);
+    }
+}
+
+#[test]
+fn bash_quotes_exe_path() {
+    let dir = test_dir("bash-exe-path");
+    // The init script has the path of the executable it came from
+    let exe_dir = dir.join("it's $(touch PWNED)");
+    fs::create_dir(&exe_dir).unwrap();
+    let exe = exe_dir.join("rps");
+    fs::copy(RPS, &exe).unwrap();
+    let script = format!(
+        "{}\n__rps_prompt_command\nprintf '%s\\n' \"$PS1\"\n\
+        if [[ -e PWNED ]]; then echo 'command ran'; fi\n",
+        init_script_from(&exe, "bash")
+    );
+    if let Some(prompt) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        assert_prompt(&prompt, &dir);
+        assert!(!prompt.contains("command ran"), "{:?}", prompt);
+    }
+}
+
+#[test]
+fn bash_chains_prompt_command() {
+    let dir = test_dir("bash-chain");
+    let script = format!(
+        "PROMPT_COMMAND='history -a'\n{0}\n{0}\ndeclare -p PROMPT_COMMAND\n",
+        init_script("bash")
+    );
+    if let Some(output) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        // Only added once, and always first
+        assert_eq!(
+            output.matches("__rps_prompt_command").count(),
+            1,
+            "{}",
+            output
+        );
+        let rps = output.find("__rps_prompt_command").unwrap();
+        assert!(rps < output.find("history -a").unwrap(), "{}", output);
+    }
+}
+
+#[test]
+fn bash_chains_prompt_command_array() {
+    let dir = test_dir("bash-chain-array");
+    let script = format!(
+        "if (( BASH_VERSINFO[0] == 5 && BASH_VERSINFO[1] < 1 || BASH_VERSINFO[0] < 5 )); then\n\
+            echo old bash\n\
+            exit\n\
+        fi\n\
+        PROMPT_COMMAND=('history -a' 'echo done')\n{}\ndeclare -p PROMPT_COMMAND\n",
+        init_script("bash")
+    );
+    if let Some(output) = run_script(
+        &dir,
+        "bash",
+        &["--noprofile", "--norc"],
+        "init.bash",
+        &script,
+    ) {
+        if output.contains("old bash") {
+            return;
+        }
+        assert!(
+            output.contains(
+                "PROMPT_COMMAND=([0]=\"__rps_prompt_command\" [1]=\"history -a\" [2]=\"echo done\")"
+            ),
+            "{}",
+            output
+        );
+    }
+}
+
+#[test]
+fn fish() {
+    let dir = test_dir("fish");
+    let script = format!("{}\nfalse\nfish_prompt\n", init_script("fish"));
+    if let Some(prompt) = run_script(&dir, "fish", &["--no-config"], "init.fish", &script) {
+        assert_prompt(&prompt, &dir);
+        assert!(prompt.contains("\u{2718} 1"), "{:?}", prompt);
+    }
+}
+
+#[test]
+fn nu() {
+    let dir = test_dir("nu");
+    let script = format!(
+        "{}\ndo --env ($env.config.hooks.pre_prompt | last)\nprint (do $env.PROMPT_COMMAND)\n",
+        init_script("nu")
+    );
+    if let Some(prompt) = run_script(&dir, "nu", &["--no-config-file"], "init.nu", &script) {
+        assert_prompt(&prompt, &dir);
+    }
+}
+
+#[test]
+fn pwsh() {
+    let dir = test_dir("pwsh");
+    let script = format!("{}\nWrite-Output (prompt)\n", init_script("pwsh"));
+    if let Some(prompt) = run_script(
+        &dir,
+        "pwsh",
+        &["-NoProfile", "-NonInteractive", "-File"],
+        "init.ps1",
+        &script,
+    ) {
+        assert_prompt(&prompt, &dir);
+    }
+}
+
+#[test]
+fn elvish() {
+    let dir = test_dir("elvish");
+    // The editor hooks only exist in interactive shells
+    let mut script: String = init_script("elvish")
+        .lines()
+        .filter(|x| !x.starts_with("set edit:"))
+        .flat_map(|x| [x, "\n"])
+        .collect();
+    script.push_str("rps-render\nprint $rps-prompts[0]\n");
+    if let Some(prompt) = run_script(&dir, "elvish", &["-norc"], "init.elv", &script) {
+        assert_prompt(&prompt, &dir);
+    }
+}
+
+#[test]
+fn xonsh() {
+    let dir = test_dir("xonsh");
+    let script = format!("{}\nprint(_rps_prompt())\n", init_script("xonsh"));
+    if let Some(prompt) = run_script(&dir, "xonsh", &["--no-rc"], "init.xsh", &script) {
+        assert_prompt(&prompt, &dir);
+    }
+}